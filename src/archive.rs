@@ -0,0 +1,275 @@
+use std::cell::OnceCell;
+use std::path::Path;
+
+use crate::compressor::{Compressor, CompressorConfig, SQFS_COMP_FLAG};
+use crate::data_reader::FileReader;
+use crate::directory_reader::{DirectoryReader, ReadDir, SQFS_DIR_READER_FLAGS};
+use crate::file::{File, SQFS_FILE_OPEN_FLAGS};
+use crate::fragment::FragmentTable;
+use crate::id::IdTable;
+use crate::info::ImageInfo;
+use crate::inode::{INode, OwnedINode};
+use crate::super_block::SuperBlock;
+use crate::xattr_reader::XattrReader;
+use crate::Result;
+
+/// Options used by [Archive] when opening an image.
+#[derive(Debug, Clone)]
+pub struct ArchiveOptions {
+    open_flags: SQFS_FILE_OPEN_FLAGS,
+    reader_flags: SQFS_DIR_READER_FLAGS,
+    load_id_table: bool,
+    load_fragment_table: bool,
+    load_directory_reader: bool,
+    load_xattr_reader: bool,
+    mmap: bool,
+    offset: u64,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        Self {
+            open_flags: SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_READ_ONLY,
+            reader_flags: SQFS_DIR_READER_FLAGS(0),
+            load_id_table: false,
+            load_fragment_table: false,
+            load_directory_reader: false,
+            load_xattr_reader: false,
+            mmap: false,
+            offset: 0,
+        }
+    }
+}
+
+impl ArchiveOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flags passed to [File::open]. Defaults to read only.
+    pub fn open_flags(mut self, flags: SQFS_FILE_OPEN_FLAGS) -> Self {
+        self.open_flags = flags;
+        self
+    }
+
+    /// Flags passed to [DirectoryReader::new].
+    pub fn reader_flags(mut self, flags: SQFS_DIR_READER_FLAGS) -> Self {
+        self.reader_flags = flags;
+        self
+    }
+
+    /// Read the [IdTable] while opening instead of on first use.
+    pub fn load_id_table(mut self, load: bool) -> Self {
+        self.load_id_table = load;
+        self
+    }
+
+    /// Read the [FragmentTable] while opening instead of on first use.
+    pub fn load_fragment_table(mut self, load: bool) -> Self {
+        self.load_fragment_table = load;
+        self
+    }
+
+    /// Create the [DirectoryReader] while opening instead of on first use.
+    pub fn load_directory_reader(mut self, load: bool) -> Self {
+        self.load_directory_reader = load;
+        self
+    }
+
+    /// Read the xattr id table while opening instead of on first use.
+    pub fn load_xattr_reader(mut self, load: bool) -> Self {
+        self.load_xattr_reader = load;
+        self
+    }
+
+    /// Read the image through [File::mmap] instead of [File::open]. The open flags are ignored.
+    #[cfg(unix)]
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Where the image starts inside the file, see [File::open_at_offset]. Defaults to `0`.
+    ///
+    /// Images at a non-zero offset are always opened read only.
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Result<Archive> {
+        #[cfg(unix)]
+        if self.mmap {
+            return Archive::from_file(File::mmap_at_offset(path, self.offset)?, self);
+        }
+
+        let file = if self.offset == 0 {
+            File::open(path, self.open_flags)?
+        } else {
+            File::open_at_offset(path, self.offset)?
+        };
+
+        Archive::from_file(file, self)
+    }
+}
+
+/// An opened image along with everything needed to read it.
+///
+/// The [IdTable], [FragmentTable], [DirectoryReader] and [XattrReader] are built the first time they are
+/// requested unless [ArchiveOptions] asks for them up front.
+pub struct Archive {
+    // Declared before the file and compressor so they are dropped first.
+    id_table: OnceCell<IdTable>,
+    fragment_table: OnceCell<FragmentTable>,
+    directory_reader: OnceCell<DirectoryReader>,
+    xattr_reader: OnceCell<XattrReader>,
+    reader_flags: SQFS_DIR_READER_FLAGS,
+    compressor: Compressor,
+    super_block: SuperBlock,
+    file: File,
+}
+
+impl Archive {
+    /// Opens the image at `path` with the default [ArchiveOptions].
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        ArchiveOptions::default().open(path)
+    }
+
+    /// Reads the image from an already opened [File].
+    pub fn from_file(file: File, options: &ArchiveOptions) -> Result<Self> {
+        let super_block = SuperBlock::read(&file)?;
+        let compressor_config = CompressorConfig::new(
+            &file,
+            &super_block,
+            SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS,
+        )?;
+        let compressor = Compressor::new(&compressor_config)?;
+
+        let archive = Self {
+            id_table: OnceCell::new(),
+            fragment_table: OnceCell::new(),
+            directory_reader: OnceCell::new(),
+            xattr_reader: OnceCell::new(),
+            reader_flags: options.reader_flags,
+            compressor,
+            super_block,
+            file,
+        };
+
+        if options.load_id_table {
+            archive.id_table()?;
+        }
+        if options.load_fragment_table {
+            archive.fragment_table()?;
+        }
+        if options.load_directory_reader {
+            archive.directory_reader()?;
+        }
+        if options.load_xattr_reader {
+            archive.xattr_reader()?;
+        }
+
+        Ok(archive)
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn super_block(&self) -> &SuperBlock {
+        &self.super_block
+    }
+
+    pub fn compressor(&self) -> &Compressor {
+        &self.compressor
+    }
+
+    /// Summary of the image, see [ImageInfo].
+    pub fn info(&self) -> Result<ImageInfo> {
+        ImageInfo::new(&self.file, &self.super_block, &self.compressor)
+    }
+
+    pub fn id_table(&self) -> Result<&IdTable> {
+        if let Some(id_table) = self.id_table.get() {
+            return Ok(id_table);
+        }
+
+        let id_table = IdTable::read(&self.file, &self.super_block, &self.compressor)?;
+
+        Ok(self.id_table.get_or_init(|| id_table))
+    }
+
+    pub fn fragment_table(&self) -> Result<&FragmentTable> {
+        if let Some(fragment_table) = self.fragment_table.get() {
+            return Ok(fragment_table);
+        }
+
+        let fragment_table = FragmentTable::read(&self.file, &self.super_block, &self.compressor)?;
+
+        Ok(self.fragment_table.get_or_init(|| fragment_table))
+    }
+
+    pub fn directory_reader(&self) -> Result<&DirectoryReader> {
+        if let Some(directory_reader) = self.directory_reader.get() {
+            return Ok(directory_reader);
+        }
+
+        let directory_reader = DirectoryReader::new(
+            &self.file,
+            &self.super_block,
+            &self.compressor,
+            self.reader_flags,
+        )?;
+
+        Ok(self.directory_reader.get_or_init(|| directory_reader))
+    }
+
+    pub fn xattr_reader(&self) -> Result<&XattrReader> {
+        if let Some(xattr_reader) = self.xattr_reader.get() {
+            return Ok(xattr_reader);
+        }
+
+        let xattr_reader = XattrReader::read(&self.file, &self.super_block, &self.compressor)?;
+
+        Ok(self.xattr_reader.get_or_init(|| xattr_reader))
+    }
+
+    /// Resolves `path` to its inode, following symbolic links inside the image.
+    pub fn lookup<P: AsRef<Path>>(&self, path: P) -> Result<OwnedINode> {
+        self.directory_reader()?.lookup(path)
+    }
+
+    /// Resolves `path` to its inode without following a symbolic link in the final component.
+    pub fn lookup_no_follow<P: AsRef<Path>>(&self, path: P) -> Result<OwnedINode> {
+        self.directory_reader()?.lookup_no_follow(path)
+    }
+
+    /// Lazily iterates over the directory at `path`, following symbolic links inside the image.
+    pub fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<ReadDir<'_>> {
+        let directory_reader = self.directory_reader()?;
+
+        directory_reader.read_dir(directory_reader.lookup(path)?)
+    }
+
+    /// Creates a [FileReader] over the contents of `inode`.
+    ///
+    /// The reader reads through the archive's [File] and [Compressor], so the archive has to
+    /// outlive it:
+    ///
+    /// ```compile_fail
+    /// use std::io::Read;
+    /// use squashed::Archive;
+    ///
+    /// let archive = Archive::open("image.sqfs").unwrap();
+    /// let inode = archive.lookup("/file").unwrap();
+    /// let inode = inode.as_inode();
+    /// let mut reader = archive.file_reader(&inode).unwrap();
+    /// drop(archive);
+    ///
+    /// let mut contents = Vec::new();
+    /// reader.read_to_end(&mut contents).unwrap();
+    /// ```
+    pub fn file_reader<'a>(&'a self, inode: &'a INode<'a>) -> Result<FileReader<'a>> {
+        FileReader::new(&self.file, &self.super_block, &self.compressor, inode)
+    }
+}
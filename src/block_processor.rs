@@ -0,0 +1,143 @@
+use std::ffi::c_void;
+use std::ptr::{self, NonNull};
+
+use crate::block_writer::BlockWriter;
+use crate::compressor::Compressor;
+pub use crate::ffi::sqfs_block_processor_stats_t;
+pub use crate::ffi::SQFS_BLK_FLAGS;
+use crate::ffi::{
+    sqfs_block_processor_append, sqfs_block_processor_begin_file, sqfs_block_processor_create,
+    sqfs_block_processor_end_file, sqfs_block_processor_finish, sqfs_block_processor_get_stats,
+    sqfs_block_processor_t, sqfs_inode_generic_t,
+};
+use crate::fragment::FragmentTable;
+use crate::inode::OwnedINode;
+use crate::{ManagedPointer, Result, SqfsError};
+
+/// Safe wrapper for [sqfs_block_processor_t]
+///
+/// Keeps pointers to the [Compressor], [BlockWriter] and [FragmentTable] it was created with,
+/// so it has to be dropped before them.
+pub struct BlockProcessor {
+    // Declared before the inodes so the workers are stopped before the inodes are freed.
+    ptr: ManagedPointer<sqfs_block_processor_t>,
+    // Boxed since the library keeps a pointer to each slot while the vector grows.
+    #[allow(clippy::vec_box)]
+    inodes: Vec<Box<InodeSlot>>,
+}
+
+impl BlockProcessor {
+    /// Safe wrapper for [sqfs_block_processor_create]
+    ///
+    /// With more than one `workers`, blocks are compressed on that many threads with copies of
+    /// `compressor`, and at most `max_backlog` blocks are kept in flight.
+    pub fn new(
+        max_block_size: u32,
+        compressor: &Compressor,
+        workers: u32,
+        max_backlog: usize,
+        block_writer: &BlockWriter,
+        fragment_table: &FragmentTable,
+    ) -> Result<Self> {
+        let init = || unsafe {
+            sqfs_block_processor_create(
+                usize::try_from(max_block_size).expect("blocksize should fit in a usize"),
+                compressor.ptr().as_ptr(),
+                workers,
+                max_backlog,
+                block_writer.ptr().as_ptr(),
+                fragment_table.ptr().as_ptr(),
+            )
+        };
+
+        ManagedPointer::check_null(&init, "Creating BlockProcessor", crate::sqfs_destroy).map(
+            |ptr| Self {
+                ptr,
+                inodes: Vec::new(),
+            },
+        )
+    }
+
+    /// Safe wrapper for [sqfs_block_processor_begin_file]
+    ///
+    /// Returns the position of the file's inode in the list returned by
+    /// [BlockProcessor::finish]. Only the user settable [SQFS_BLK_FLAGS] are allowed.
+    pub fn begin_file(&mut self, flags: SQFS_BLK_FLAGS) -> Result<usize> {
+        let mut slot = Box::new(InodeSlot(ptr::null_mut()));
+
+        let code = unsafe {
+            sqfs_block_processor_begin_file(
+                self.ptr.as_ptr(),
+                &mut slot.0,
+                ptr::null_mut(),
+                flags.0,
+            )
+        };
+
+        crate::sqfs_check(code, "Beginning file in BlockProcessor")?;
+
+        // The processor keeps writing to the slot until it is finished, so it must not move.
+        self.inodes.push(slot);
+
+        Ok(self.inodes.len() - 1)
+    }
+
+    /// Safe wrapper for [sqfs_block_processor_append]
+    pub fn append(&mut self, data: &[u8]) -> Result<()> {
+        let code = unsafe {
+            sqfs_block_processor_append(
+                self.ptr.as_ptr(),
+                data.as_ptr() as *const c_void,
+                data.len(),
+            )
+        };
+
+        crate::sqfs_check(code, "Appending data to BlockProcessor").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_block_processor_end_file]
+    pub fn end_file(&mut self) -> Result<()> {
+        let code = unsafe { sqfs_block_processor_end_file(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Ending file in BlockProcessor").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_block_processor_finish]
+    ///
+    /// Waits for every block to be written and returns the completed file inodes in the order
+    /// the files were begun.
+    pub fn finish(&mut self) -> Result<Vec<OwnedINode>> {
+        let code = unsafe { sqfs_block_processor_finish(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Finishing BlockProcessor")?;
+
+        self.inodes
+            .drain(..)
+            .map(|mut slot| {
+                NonNull::new(std::mem::replace(&mut slot.0, ptr::null_mut()))
+                    .map(|ptr| OwnedINode::new(ManagedPointer::new(ptr, crate::sqfs_free)))
+                    .ok_or_else(|| {
+                        SqfsError::LibraryReturnError("Finishing BlockProcessor".to_string())
+                    })
+            })
+            .collect()
+    }
+
+    /// Safe wrapper for [sqfs_block_processor_get_stats]
+    ///
+    /// Only complete once [BlockProcessor::finish] returned.
+    pub fn stats(&self) -> sqfs_block_processor_stats_t {
+        unsafe { *sqfs_block_processor_get_stats(self.ptr.as_ptr()) }
+    }
+}
+
+/// Where the block processor stores the inode of a file, which is ours to free.
+struct InodeSlot(*mut sqfs_inode_generic_t);
+
+impl Drop for InodeSlot {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            crate::sqfs_free(self.0);
+        }
+    }
+}
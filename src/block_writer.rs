@@ -0,0 +1,46 @@
+pub use crate::ffi::SQFS_BLOCK_WRITER_FLAGS;
+use crate::ffi::{sqfs_block_writer_create, sqfs_block_writer_t};
+use crate::file::File;
+use crate::{ManagedPointer, Result};
+
+/// Safe wrapper for [sqfs_block_writer_t]
+///
+/// Keeps a pointer to the [File] it appends blocks to, so it has to be dropped before the file.
+pub struct BlockWriter {
+    ptr: ManagedPointer<sqfs_block_writer_t>,
+}
+
+impl BlockWriter {
+    /// Safe wrapper for [sqfs_block_writer_create]
+    ///
+    /// Blocks flagged for alignment are padded to a multiple of `device_block_size`.
+    pub fn new(
+        file: &File,
+        device_block_size: usize,
+        flags: SQFS_BLOCK_WRITER_FLAGS,
+    ) -> Result<Self> {
+        let init =
+            || unsafe { sqfs_block_writer_create(file.ptr().as_ptr(), device_block_size, flags.0) };
+
+        ManagedPointer::check_null(&init, "Creating BlockWriter", crate::sqfs_destroy)
+            .map(|ptr| Self { ptr })
+    }
+
+    /// Number of blocks actually written, which excludes blocks that were deduplicated.
+    pub fn block_count(&self) -> u64 {
+        let get_block_count = self
+            .as_ref()
+            .get_block_count
+            .expect("missing get_block_count function on the block writer");
+
+        unsafe { get_block_count(self.ptr.as_ptr()) }
+    }
+
+    pub(crate) fn ptr(&self) -> &ManagedPointer<sqfs_block_writer_t> {
+        &self.ptr
+    }
+
+    fn as_ref(&self) -> &sqfs_block_writer_t {
+        unsafe { &(*self.ptr.as_ptr()) }
+    }
+}
@@ -1,4 +1,6 @@
-use crate::InternalBlockSize;
+use crate::compressor::Compressor;
+use crate::file::File;
+use crate::{BlockAttributes, InternalBlockSize, Result};
 
 #[derive(Debug, Clone)]
 pub struct Block {
@@ -14,6 +16,30 @@ impl Block {
     pub fn start_offset(&self) -> u64 {
         self.start_offset
     }
+
+    /// Reads and, if needed, decompresses the block.
+    ///
+    /// A sparse block is returned as a full block of zeros even though the last block of a file
+    /// may be shorter. The compressor must be created with
+    /// [SQFS_COMP_FLAG_UNCOMPRESS](crate::compressor::SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS).
+    pub fn read(&self, file: &File, compressor: &Compressor) -> Result<Vec<u8>> {
+        if self.is_sparse() {
+            let block_size = compressor.get_configuration().block_size();
+
+            return Ok(vec![
+                0u8;
+                usize::try_from(block_size)
+                    .expect("blocksize should fit in a usize")
+            ]);
+        }
+
+        read(file, compressor, self.start_offset, self)
+    }
+
+    /// Reads the block exactly as it is stored in the image. Sparse blocks are empty.
+    pub fn read_raw(&self, file: &File) -> Result<Vec<u8>> {
+        read_raw(file, self.start_offset, self)
+    }
 }
 
 impl InternalBlockSize for Block {
@@ -21,3 +47,36 @@ impl InternalBlockSize for Block {
         self.size
     }
 }
+
+/// Reads the on disk data of a [Block] or [Fragment](crate::fragment::Fragment) block.
+pub(crate) fn read_raw<B: BlockAttributes>(
+    file: &File,
+    start_offset: u64,
+    block: &B,
+) -> Result<Vec<u8>> {
+    let size = usize::try_from(block.size()).expect("block size should fit in a usize");
+
+    file.read_at(start_offset, size).map(Vec::from)
+}
+
+/// Reads the data of a [Block] or [Fragment](crate::fragment::Fragment) block and decompresses
+/// it if it is stored compressed.
+pub(crate) fn read<B: BlockAttributes>(
+    file: &File,
+    compressor: &Compressor,
+    start_offset: u64,
+    block: &B,
+) -> Result<Vec<u8>> {
+    let data = read_raw(file, start_offset, block)?;
+
+    if !block.is_compressed() {
+        return Ok(data);
+    }
+
+    let block_size = compressor.get_configuration().block_size();
+
+    compressor.decompress(
+        &data,
+        usize::try_from(block_size).expect("blocksize should fit in a usize"),
+    )
+}
@@ -0,0 +1,242 @@
+use std::ffi::CString;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use crate::file::File;
+use crate::writer::{ImageWriter, Node, NodeKind, WriterOptions, WriterStats};
+use crate::xattr_writer::XattrWriter;
+use crate::{Result, SqfsError};
+
+/// Size of the chunks [ImageBuilder::finish_into] copies the image in.
+const COPY_BUF_SIZE: usize = 65536;
+
+/// Metadata of an entry added to an [ImageBuilder].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    mode: Option<u16>,
+    uid: u32,
+    gid: u32,
+    modification_time: Option<u32>,
+    xattrs: Vec<(String, Vec<u8>)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Permission bits, the file type is ignored. Defaults to `0o755` for directories and
+    /// `0o644` for everything else.
+    pub fn mode(mut self, mode: u16) -> Self {
+        self.mode = Some(mode & 0o7777);
+        self
+    }
+
+    /// Defaults to `0`.
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = uid;
+        self
+    }
+
+    /// Defaults to `0`.
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = gid;
+        self
+    }
+
+    /// Defaults to the modification time of the image.
+    pub fn modification_time(mut self, modification_time: u32) -> Self {
+        self.modification_time = Some(modification_time);
+        self
+    }
+
+    /// Adds an extended attribute such as `user.comment`.
+    ///
+    /// Ignored if the image is written without extended attributes.
+    pub fn xattr<K: Into<String>>(mut self, key: K, value: &[u8]) -> Self {
+        self.xattrs.push((key.into(), value.to_vec()));
+        self
+    }
+}
+
+/// Kinds of device that [ImageBuilder::add_device] can add.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceType {
+    Block,
+    Character,
+}
+
+/// Combines a major and minor number into a device number the way Linux encodes them.
+pub fn device_number(major: u32, minor: u32) -> u32 {
+    (minor & 0xff) | ((major & 0xfff) << 8) | ((minor & !0xff) << 12)
+}
+
+/// Builds an image from entries described one at a time instead of a directory on disk.
+///
+/// Paths are relative to the root of the image and missing parent directories are created
+/// owned by root with mode `0o755`. Describing such a directory later with
+/// [ImageBuilder::add_dir] updates its metadata. File contents are compressed as they are
+/// added.
+pub struct ImageBuilder {
+    writer: ImageWriter,
+}
+
+impl ImageBuilder {
+    /// Builds the image in `file`, which should be empty.
+    pub fn new(file: File, options: &WriterOptions) -> Result<Self> {
+        Ok(Self {
+            writer: ImageWriter::new(file, options)?,
+        })
+    }
+
+    /// Builds the image in memory, to be written out with [ImageBuilder::finish_into].
+    pub fn in_memory(options: &WriterOptions) -> Result<Self> {
+        Self::new(File::from_io(Box::new(Vec::<u8>::new())), options)
+    }
+
+    /// Adds a directory, or updates the metadata of one that is already there.
+    ///
+    /// An empty `path` or `/` refers to the root.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, path: P, meta: &Metadata) -> Result<()> {
+        self.add(path.as_ref(), NodeKind::Directory(Default::default()), meta)
+    }
+
+    /// Adds a regular file with everything `contents` returns.
+    pub fn add_file<P: AsRef<Path>, R: Read>(
+        &mut self,
+        path: P,
+        meta: &Metadata,
+        mut contents: R,
+    ) -> Result<()> {
+        self.writer.check_finished()?;
+
+        // Nothing is written for an entry that would be refused, the blocks would be left over.
+        let path = path.as_ref();
+        xattrs(meta)?;
+        self.writer.check_vacant(path)?;
+        let index = self.writer.write_file(&mut contents)?;
+        self.add(path, NodeKind::File(index), meta)
+    }
+
+    /// Adds a symbolic link pointing at `target`, which is stored as is.
+    pub fn add_symlink<P: AsRef<Path>, T: AsRef<Path>>(
+        &mut self,
+        path: P,
+        meta: &Metadata,
+        target: T,
+    ) -> Result<()> {
+        let target = crate::writer::name_bytes(target.as_ref().as_os_str())?;
+
+        self.add(path.as_ref(), NodeKind::SymbolicLink(target), meta)
+    }
+
+    /// Adds a device node, see [device_number] for building `device_number`.
+    pub fn add_device<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        meta: &Metadata,
+        device_type: DeviceType,
+        device_number: u32,
+    ) -> Result<()> {
+        let kind = match device_type {
+            DeviceType::Block => NodeKind::BlockDevice(device_number),
+            DeviceType::Character => NodeKind::CharacterDevice(device_number),
+        };
+
+        self.add(path.as_ref(), kind, meta)
+    }
+
+    pub fn add_fifo<P: AsRef<Path>>(&mut self, path: P, meta: &Metadata) -> Result<()> {
+        self.add(path.as_ref(), NodeKind::Fifo, meta)
+    }
+
+    pub fn add_socket<P: AsRef<Path>>(&mut self, path: P, meta: &Metadata) -> Result<()> {
+        self.add(path.as_ref(), NodeKind::Socket, meta)
+    }
+
+    /// Adds `path` as another name for the entry at `target`, which has to be added already
+    /// and can't be a directory. Both share the metadata of `target`.
+    pub fn add_hard_link<P: AsRef<Path>, T: AsRef<Path>>(
+        &mut self,
+        path: P,
+        target: T,
+    ) -> Result<()> {
+        self.writer.check_finished()?;
+
+        self.writer.link_path(path.as_ref(), target.as_ref())
+    }
+
+    /// Statistics about the file contents added, see [ImageWriter::stats].
+    pub fn stats(&self) -> WriterStats {
+        self.writer.stats()
+    }
+
+    /// Writes the rest of the image to the [File] it was created with.
+    pub fn finish(&mut self) -> Result<()> {
+        self.writer.finish()
+    }
+
+    /// Finishes the image and copies all of it into `sink`.
+    ///
+    /// Mostly useful with [ImageBuilder::in_memory], since the image has to be written to a
+    /// [File] first: the super block at the start is only known at the very end.
+    pub fn finish_into<W: Write>(&mut self, mut sink: W) -> Result<()> {
+        self.finish()?;
+
+        let file = self.writer.file();
+        let size = file.get_size();
+        let mut offset = 0;
+        while offset < size {
+            let chunk = usize::try_from(size - offset)
+                .unwrap_or(usize::MAX)
+                .min(COPY_BUF_SIZE);
+            sink.write_all(&file.read_at(offset, chunk)?)?;
+            offset += u64::try_from(chunk).expect("usize should fit in u64");
+        }
+
+        sink.flush()?;
+
+        Ok(())
+    }
+
+    fn add(&mut self, path: &Path, kind: NodeKind, meta: &Metadata) -> Result<()> {
+        self.writer.check_finished()?;
+
+        let default_mode = match kind {
+            NodeKind::Directory(_) => 0o755,
+            _ => 0o644,
+        };
+        let xattrs = xattrs(meta)?;
+
+        let node = Node {
+            kind,
+            mode: meta.mode.unwrap_or(default_mode),
+            uid_index: self.writer.id_index(meta.uid)?,
+            gid_index: self.writer.id_index(meta.gid)?,
+            modification_time: meta
+                .modification_time
+                .unwrap_or_else(|| self.writer.modification_time()),
+            xattr_index: self.writer.store_xattrs(&xattrs)?,
+            links: 0,
+        };
+
+        self.writer.insert_path(path, node)
+    }
+}
+
+/// The extended attributes of `meta`, refusing those squashfs can't store.
+fn xattrs(meta: &Metadata) -> Result<Vec<(CString, Vec<u8>)>> {
+    let mut xattrs = Vec::with_capacity(meta.xattrs.len());
+    for (key, value) in &meta.xattrs {
+        let key = CString::new(key.as_str())?;
+        if !XattrWriter::supports(&key) {
+            return Err(SqfsError::Unsupported(format!(
+                "extended attribute {}",
+                key.to_string_lossy()
+            )));
+        }
+        xattrs.push((key, value.clone()));
+    }
+
+    Ok(xattrs)
+}
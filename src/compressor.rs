@@ -1,17 +1,21 @@
+use std::fmt;
+
 use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::ToPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::ffi::SQFS_COMPRESSOR::*;
 pub use crate::ffi::SQFS_COMP_FLAG;
+use crate::ffi::SQFS_LZO_ALGORITHM::*;
 use crate::ffi::{
     sqfs_compressor_config_init, sqfs_compressor_config_t, sqfs_compressor_create,
     sqfs_compressor_t,
 };
-use crate::super_block::SuperBlock;
-use crate::{ManagedPointer, Result};
+use crate::file::File;
+use crate::super_block::{SuperBlock, SuperFlags};
+use crate::{ManagedPointer, Result, SqfsError};
 
 /// The type of compression used in the image.
-#[derive(Debug, FromPrimitive, ToPrimitive, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, FromPrimitive, ToPrimitive, Eq, PartialEq)]
 #[repr(u32)]
 pub enum CompressorType {
     GZip = SQFS_COMP_GZIP,
@@ -22,22 +26,65 @@ pub enum CompressorType {
     Zstd = SQFS_COMP_ZSTD,
 }
 
+/// Lower case name like mksquashfs uses for `-comp`.
+impl fmt::Display for CompressorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::GZip => "gzip",
+            Self::Lzma => "lzma",
+            Self::Lzo => "lzo",
+            Self::Xz => "xz",
+            Self::Lz4 => "lz4",
+            Self::Zstd => "zstd",
+        };
+
+        f.write_str(name)
+    }
+}
+
 /// Safe warapper for [sqfs_compressor_config_t]
+#[derive(Clone)]
 pub struct CompressorConfig {
     compressor_config: sqfs_compressor_config_t,
 }
 
 impl CompressorConfig {
     /// Safe wrapper for [sqfs_compressor_config_init]
-    pub fn new(super_block: &SuperBlock, flags: SQFS_COMP_FLAG) -> Result<Self> {
+    ///
+    /// If the super block has [SuperFlags::COMPRESSOR_OPTIONS] set, the options
+    /// stored in `file` right after it replace the defaults.
+    pub fn new(file: &File, super_block: &SuperBlock, flags: SQFS_COMP_FLAG) -> Result<Self> {
+        let config = Self::init(
+            &super_block.compression_id(),
+            super_block.block_size(),
+            flags,
+        )?;
+
+        if !super_block.flags().contains(SuperFlags::COMPRESSOR_OPTIONS) {
+            return Ok(config);
+        }
+
+        let compressor = Compressor::new(&config)?;
+        compressor.read_options(file)?;
+
+        // Keep the flags that only affect this process, like whether we decompress.
+        let mut config = compressor.get_configuration();
+        config.compressor_config.flags |= u16::try_from(flags.0).expect("flags should fit in u16");
+
+        Ok(config)
+    }
+
+    /// The defaults libsquashfs picks for `compressor_type`.
+    fn init(
+        compressor_type: &CompressorType,
+        block_size: u32,
+        flags: SQFS_COMP_FLAG,
+    ) -> Result<Self> {
         let init = |ptr| unsafe {
             sqfs_compressor_config_init(
                 ptr,
-                super_block
-                    .compression_id()
-                    .to_u32()
-                    .expect("invalid compression type"),
-                usize::try_from(super_block.block_size()).expect("blocksize should fit in a usize"),
+                compressor_type.to_u32().expect("invalid compression type"),
+                usize::try_from(block_size).expect("blocksize should fit in a usize"),
                 u16::try_from(flags.0).expect("flags should fit in u16"),
             )
         };
@@ -46,6 +93,80 @@ impl CompressorConfig {
 
         Ok(Self { compressor_config })
     }
+
+    /// Makes compressors created from this configuration decompress instead.
+    pub fn uncompress(mut self) -> Self {
+        self.compressor_config.flags |= u16::try_from(SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS.0)
+            .expect("flag should fit in u16");
+        self
+    }
+
+    pub fn compressor_type(&self) -> CompressorType {
+        CompressorType::from_u16(self.compressor_config.id).expect("invalid compression algorithm")
+    }
+
+    pub fn flags(&self) -> SQFS_COMP_FLAG {
+        SQFS_COMP_FLAG(u32::from(self.compressor_config.flags))
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.compressor_config.block_size
+    }
+
+    pub fn level(&self) -> u32 {
+        self.compressor_config.level
+    }
+
+    /// Only set for [CompressorType::GZip].
+    pub fn window_size(&self) -> Option<u16> {
+        match self.compressor_type() {
+            CompressorType::GZip => Some(unsafe { self.compressor_config.opt.gzip.window_size }),
+            _ => None,
+        }
+    }
+
+    /// Only set for [CompressorType::Xz] and [CompressorType::Lzma].
+    pub fn dict_size(&self) -> Option<u32> {
+        match self.compressor_type() {
+            CompressorType::Xz => Some(unsafe { self.compressor_config.opt.xz.dict_size }),
+            CompressorType::Lzma => Some(unsafe { self.compressor_config.opt.lzma.dict_size }),
+            _ => None,
+        }
+    }
+
+    /// Only set for [CompressorType::Lzo].
+    pub fn lzo_algorithm(&self) -> Option<LzoAlgorithm> {
+        match self.compressor_type() {
+            CompressorType::Lzo => {
+                LzoAlgorithm::from_u16(unsafe { self.compressor_config.opt.lzo.algorithm })
+            }
+            _ => None,
+        }
+    }
+
+    /// Empty for anything but [CompressorType::GZip].
+    pub fn gzip_strategies(&self) -> Vec<GzipStrategy> {
+        if self.compressor_type() != CompressorType::GZip {
+            return Vec::new();
+        }
+
+        GzipStrategy::ALL
+            .into_iter()
+            .filter(|strategy| self.flags().0 & strategy.flag().0 != 0)
+            .collect()
+    }
+
+    /// Empty for anything but [CompressorType::Xz].
+    pub fn xz_filters(&self) -> Vec<XzFilter> {
+        if self.compressor_type() != CompressorType::Xz {
+            return Vec::new();
+        }
+
+        XzFilter::ALL
+            .into_iter()
+            .filter(|filter| self.flags().0 & filter.flag().0 != 0)
+            .collect()
+    }
 }
 
 /// Safe wrapper for [sqfs_compressor_t]
@@ -53,6 +174,9 @@ pub struct Compressor {
     ptr: ManagedPointer<sqfs_compressor_t>,
 }
 
+// The compressor is stateful but exclusively owned, so it can be moved to another thread.
+unsafe impl Send for Compressor {}
+
 impl Compressor {
     /// Safe wrapper for [sqfs_compressor_create]
     pub fn new(compressor_config: &CompressorConfig) -> Result<Self> {
@@ -63,16 +187,595 @@ impl Compressor {
             .map(|ptr| Self { ptr })
     }
 
-    // TODO: implement get_configuration
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__compressor__t.html#a7cbb0194b79880c229bcec8241a5f19b
+    /// Creates a compressor with the same configuration that doesn't share any state with this
+    /// one.
+    pub fn try_clone(&self) -> Result<Self> {
+        let ptr = crate::sqfs_copy(self.ptr.as_ptr())?;
+
+        Ok(Self {
+            ptr: ManagedPointer::new(ptr, crate::sqfs_destroy),
+        })
+    }
+
+    /// The configuration the compressor is currently using.
+    pub fn get_configuration(&self) -> CompressorConfig {
+        let get_configuration = self
+            .as_ref()
+            .get_configuration
+            .expect("missing get_configuration function on the compressor");
+
+        let mut compressor_config = sqfs_compressor_config_t::default();
+        unsafe { get_configuration(self.ptr.as_ptr(), &mut compressor_config) };
+
+        CompressorConfig { compressor_config }
+    }
+
+    /// Reads the options stored right after the super block and applies them to the compressor.
+    ///
+    /// Only images with [SuperFlags::COMPRESSOR_OPTIONS] set have options stored.
+    pub fn read_options(&self, file: &File) -> Result<()> {
+        let read_options = self
+            .as_ref()
+            .read_options
+            .expect("missing read_options function on the compressor");
+
+        let code = unsafe { read_options(self.ptr.as_ptr(), file.ptr().as_ptr()) };
+
+        crate::sqfs_check(code, "Reading compressor options").map(|_| ())
+    }
+
+    /// Writes the options of the compressor right after the super block.
+    ///
+    /// Returns the number of bytes written, which is `0` if the options are the defaults. If
+    /// anything was written, [SuperFlags::COMPRESSOR_OPTIONS] has to be set in
+    /// the super block.
+    pub fn write_options(&self, file: &File) -> Result<usize> {
+        let write_options = self
+            .as_ref()
+            .write_options
+            .expect("missing write_options function on the compressor");
+
+        let code = unsafe { write_options(self.ptr.as_ptr(), file.ptr().as_ptr()) };
+
+        crate::sqfs_check(code, "Writing compressor options")
+            .map(|written| usize::try_from(written).expect("positive i32 should fit in usize"))
+    }
+
+    /// Compresses a single block of data.
+    ///
+    /// The compressor must not have been created with [SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS].
+    pub fn compress(&self, data: &[u8]) -> Result<CompressedBlock> {
+        let mut out = vec![0u8; data.len()];
+        let written = self.do_block(data, &mut out, "Compressing block")?;
+
+        if written == 0 {
+            return Ok(CompressedBlock::Uncompressed(data.to_vec()));
+        }
+
+        out.truncate(written);
+
+        Ok(CompressedBlock::Compressed(out))
+    }
+
+    /// Decompresses a single block of data into at most `max_out` bytes, usually the block size.
+    ///
+    /// The compressor must have been created with [SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS].
+    pub fn decompress(&self, data: &[u8], max_out: usize) -> Result<Vec<u8>> {
+        let mut out = vec![0u8; max_out];
+        let written = self.do_block(data, &mut out, "Decompressing block")?;
 
-    // TODO: implement read_options
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__compressor__t.html#a15e937a2611ef6d9abc7f77fae2c11c8
+        out.truncate(written);
+
+        Ok(out)
+    }
+
+    fn do_block(&self, data: &[u8], out: &mut [u8], desc: &str) -> Result<usize> {
+        let do_block = self
+            .as_ref()
+            .do_block
+            .expect("missing do_block function on the compressor");
+        let size = u32::try_from(data.len())
+            .map_err(|_| SqfsError::LibraryError(desc.to_string(), crate::LibError::Overflow))?;
+        let out_size = u32::try_from(out.len()).unwrap_or(u32::MAX);
+
+        let code = unsafe {
+            do_block(
+                self.ptr.as_ptr(),
+                data.as_ptr(),
+                size,
+                out.as_mut_ptr(),
+                out_size,
+            )
+        };
 
-    // TODO: implement write_options
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__compressor__t.html#a956781a777f8e304331d8b02295ded9e
+        crate::sqfs_check(code, desc)
+            .map(|written| usize::try_from(written).expect("positive i32 should fit in usize"))
+    }
 
     pub(crate) fn ptr(&self) -> &ManagedPointer<sqfs_compressor_t> {
         &self.ptr
     }
+
+    fn as_ref(&self) -> &sqfs_compressor_t {
+        unsafe { &(*self.ptr.as_ptr()) }
+    }
+}
+
+/// Outcome of [Compressor::compress].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressedBlock {
+    /// The data shrunk and is stored compressed.
+    Compressed(Vec<u8>),
+    /// Compressing did not shrink the data, so it is stored as it was.
+    Uncompressed(Vec<u8>),
+}
+
+impl CompressedBlock {
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, Self::Compressed(_))
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Compressed(data) | Self::Uncompressed(data) => data,
+        }
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        match self {
+            Self::Compressed(data) | Self::Uncompressed(data) => data,
+        }
+    }
+}
+
+/// Typed options for one compression algorithm, used to configure a compressor for writing.
+pub trait CompressorOptions {
+    /// Validates the options against the limits of libsquashfs and builds a [CompressorConfig]
+    /// for images with `block_size` sized blocks.
+    fn config(&self, block_size: u32) -> Result<CompressorConfig>;
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    name: &str,
+    value: T,
+    min: T,
+    max: T,
+) -> Result<T> {
+    if value < min || value > max {
+        return Err(SqfsError::CompressorOption(format!(
+            "{} must be between {} and {}, got {}",
+            name, min, max, value
+        )));
+    }
+
+    Ok(value)
+}
+
+/// Checks that an xz or lzma dictionary size is `2^n` or `2^n + 2^(n-1)` and no larger than a
+/// block, like mksquashfs and the kernel expect.
+fn check_dict_size(dict_size: u32, block_size: u32) -> Result<u32> {
+    let dict_size = check_range("dictionary size", dict_size, 8192, block_size.max(8192))?;
+    let high = dict_size & (dict_size - 1);
+
+    if high != 0 && dict_size != high | (high >> 1) {
+        return Err(SqfsError::CompressorOption(format!(
+            "dictionary size must be 2^n or 2^n + 2^(n-1), got {}",
+            dict_size
+        )));
+    }
+
+    Ok(dict_size)
+}
+
+/// Strategies zlib tries for every block, keeping whichever result is smallest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GzipStrategy {
+    Default,
+    Filtered,
+    Huffman,
+    Rle,
+    Fixed,
+}
+
+impl GzipStrategy {
+    const ALL: [Self; 5] = [
+        Self::Default,
+        Self::Filtered,
+        Self::Huffman,
+        Self::Rle,
+        Self::Fixed,
+    ];
+
+    fn flag(self) -> SQFS_COMP_FLAG {
+        match self {
+            Self::Default => SQFS_COMP_FLAG::SQFS_COMP_FLAG_GZIP_DEFAULT,
+            Self::Filtered => SQFS_COMP_FLAG::SQFS_COMP_FLAG_GZIP_FILTERED,
+            Self::Huffman => SQFS_COMP_FLAG::SQFS_COMP_FLAG_GZIP_HUFFMAN,
+            Self::Rle => SQFS_COMP_FLAG::SQFS_COMP_FLAG_GZIP_RLE,
+            Self::Fixed => SQFS_COMP_FLAG::SQFS_COMP_FLAG_GZIP_FIXED,
+        }
+    }
+}
+
+/// Options for [CompressorType::GZip].
+#[derive(Debug, Clone)]
+pub struct GzipOptions {
+    level: u32,
+    window_size: u16,
+    strategies: Vec<GzipStrategy>,
+}
+
+impl Default for GzipOptions {
+    fn default() -> Self {
+        Self {
+            level: 9,
+            window_size: 15,
+            strategies: Vec::new(),
+        }
+    }
+}
+
+impl GzipOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Between 1 and 9. Defaults to 9.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Base two logarithm of the window size, between 8 and 15. Defaults to 15.
+    pub fn window_size(mut self, window_size: u16) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// Defaults to only the default strategy.
+    pub fn strategies(mut self, strategies: &[GzipStrategy]) -> Self {
+        self.strategies = strategies.to_vec();
+        self
+    }
+}
+
+impl CompressorOptions for GzipOptions {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let flags = self
+            .strategies
+            .iter()
+            .fold(SQFS_COMP_FLAG(0), |flags, strategy| flags | strategy.flag());
+        let mut config = CompressorConfig::init(&CompressorType::GZip, block_size, flags)?;
+
+        config.compressor_config.level = check_range("gzip level", self.level, 1, 9)?;
+        config.compressor_config.opt.gzip.window_size =
+            check_range("gzip window size", self.window_size, 8, 15)?;
+
+        Ok(config)
+    }
+}
+
+/// Branch/call/jump filters xz tries for every block, keeping whichever result is smallest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XzFilter {
+    X86,
+    PowerPc,
+    Ia64,
+    Arm,
+    ArmThumb,
+    Sparc,
+}
+
+impl XzFilter {
+    const ALL: [Self; 6] = [
+        Self::X86,
+        Self::PowerPc,
+        Self::Ia64,
+        Self::Arm,
+        Self::ArmThumb,
+        Self::Sparc,
+    ];
+
+    fn flag(self) -> SQFS_COMP_FLAG {
+        match self {
+            Self::X86 => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_X86,
+            Self::PowerPc => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_POWERPC,
+            Self::Ia64 => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_IA64,
+            Self::Arm => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_ARM,
+            Self::ArmThumb => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_ARMTHUMB,
+            Self::Sparc => SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_SPARC,
+        }
+    }
+}
+
+/// Options for [CompressorType::Xz].
+#[derive(Debug, Clone)]
+pub struct XzOptions {
+    dict_size: Option<u32>,
+    filters: Vec<XzFilter>,
+    level: u32,
+    extreme: bool,
+}
+
+impl Default for XzOptions {
+    fn default() -> Self {
+        Self {
+            dict_size: None,
+            filters: Vec::new(),
+            level: 6,
+            extreme: false,
+        }
+    }
+}
+
+impl XzOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At least 8 KiB and at most the block size, which is the default. Has to be `2^n` or
+    /// `2^n + 2^(n-1)`.
+    pub fn dict_size(mut self, dict_size: u32) -> Self {
+        self.dict_size = Some(dict_size);
+        self
+    }
+
+    /// Defaults to no filters.
+    pub fn filters(mut self, filters: &[XzFilter]) -> Self {
+        self.filters = filters.to_vec();
+        self
+    }
+
+    /// Between 0 and 9. Defaults to 6.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Spend more time trying to compress better.
+    pub fn extreme(mut self, extreme: bool) -> Self {
+        self.extreme = extreme;
+        self
+    }
+}
+
+impl CompressorOptions for XzOptions {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let mut flags = self
+            .filters
+            .iter()
+            .fold(SQFS_COMP_FLAG(0), |flags, filter| flags | filter.flag());
+        if self.extreme {
+            flags |= SQFS_COMP_FLAG::SQFS_COMP_FLAG_XZ_EXTREME;
+        }
+        let mut config = CompressorConfig::init(&CompressorType::Xz, block_size, flags)?;
+
+        config.compressor_config.level = check_range("xz level", self.level, 0, 9)?;
+        config.compressor_config.opt.xz.dict_size =
+            check_dict_size(self.dict_size.unwrap_or(block_size), block_size)?;
+
+        Ok(config)
+    }
+}
+
+/// Options for [CompressorType::Zstd].
+#[derive(Debug, Clone)]
+pub struct ZstdOptions {
+    level: u32,
+}
+
+impl Default for ZstdOptions {
+    fn default() -> Self {
+        Self { level: 15 }
+    }
+}
+
+impl ZstdOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Between 1 and 22. Defaults to 15.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+}
+
+impl CompressorOptions for ZstdOptions {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let mut config =
+            CompressorConfig::init(&CompressorType::Zstd, block_size, SQFS_COMP_FLAG(0))?;
+
+        config.compressor_config.level = check_range("zstd level", self.level, 1, 22)?;
+
+        Ok(config)
+    }
+}
+
+/// Options for [CompressorType::Lz4].
+#[derive(Debug, Clone, Default)]
+pub struct Lz4Options {
+    hc: bool,
+}
+
+impl Lz4Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the slower high compression variant.
+    pub fn hc(mut self, hc: bool) -> Self {
+        self.hc = hc;
+        self
+    }
+}
+
+impl CompressorOptions for Lz4Options {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let flags = if self.hc {
+            SQFS_COMP_FLAG::SQFS_COMP_FLAG_LZ4_HC
+        } else {
+            SQFS_COMP_FLAG(0)
+        };
+
+        CompressorConfig::init(&CompressorType::Lz4, block_size, flags)
+    }
+}
+
+/// Variants of LZO. Only [LzoAlgorithm::Lzo1x999] supports a compression level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[repr(u32)]
+pub enum LzoAlgorithm {
+    Lzo1x1 = SQFS_LZO1X_1,
+    Lzo1x1_11 = SQFS_LZO1X_1_11,
+    Lzo1x1_12 = SQFS_LZO1X_1_12,
+    Lzo1x1_15 = SQFS_LZO1X_1_15,
+    Lzo1x999 = SQFS_LZO1X_999,
+}
+
+/// Options for [CompressorType::Lzo].
+#[derive(Debug, Clone)]
+pub struct LzoOptions {
+    algorithm: LzoAlgorithm,
+    level: Option<u32>,
+}
+
+impl Default for LzoOptions {
+    fn default() -> Self {
+        Self {
+            algorithm: LzoAlgorithm::Lzo1x999,
+            level: None,
+        }
+    }
+}
+
+impl LzoOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defaults to [LzoAlgorithm::Lzo1x999].
+    pub fn algorithm(mut self, algorithm: LzoAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Between 1 and 9 for [LzoAlgorithm::Lzo1x999]. Defaults to 8.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = Some(level);
+        self
+    }
+}
+
+impl CompressorOptions for LzoOptions {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let mut config =
+            CompressorConfig::init(&CompressorType::Lzo, block_size, SQFS_COMP_FLAG(0))?;
+
+        config.compressor_config.opt.lzo.algorithm =
+            u16::try_from(self.algorithm as u32).expect("algorithm should fit in u16");
+        config.compressor_config.level = match (self.algorithm, self.level) {
+            (LzoAlgorithm::Lzo1x999, level) => check_range("lzo level", level.unwrap_or(8), 1, 9)?,
+            (_, None) => 0,
+            (algorithm, Some(_)) => {
+                return Err(SqfsError::CompressorOption(format!(
+                    "{:?} does not support a compression level",
+                    algorithm
+                )))
+            }
+        };
+
+        Ok(config)
+    }
+}
+
+/// Options for [CompressorType::Lzma], the legacy format that predates xz.
+#[derive(Debug, Clone)]
+pub struct LzmaOptions {
+    dict_size: Option<u32>,
+    level: u32,
+    extreme: bool,
+    lc: u8,
+    lp: u8,
+    pb: u8,
+}
+
+impl Default for LzmaOptions {
+    fn default() -> Self {
+        Self {
+            dict_size: None,
+            level: 6,
+            extreme: false,
+            lc: 3,
+            lp: 0,
+            pb: 2,
+        }
+    }
+}
+
+impl LzmaOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// At least 8 KiB and at most the block size, which is the default. Has to be `2^n` or
+    /// `2^n + 2^(n-1)`.
+    pub fn dict_size(mut self, dict_size: u32) -> Self {
+        self.dict_size = Some(dict_size);
+        self
+    }
+
+    /// Between 0 and 9. Defaults to 6.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Spend more time trying to compress better.
+    pub fn extreme(mut self, extreme: bool) -> Self {
+        self.extreme = extreme;
+        self
+    }
+
+    /// Number of literal context bits, at most 4 together with [LzmaOptions::lp]. Defaults to 3.
+    pub fn lc(mut self, lc: u8) -> Self {
+        self.lc = lc;
+        self
+    }
+
+    /// Number of literal position bits, at most 4 together with [LzmaOptions::lc]. Defaults
+    /// to 0.
+    pub fn lp(mut self, lp: u8) -> Self {
+        self.lp = lp;
+        self
+    }
+
+    /// Number of position bits, at most 4. Defaults to 2.
+    pub fn pb(mut self, pb: u8) -> Self {
+        self.pb = pb;
+        self
+    }
+}
+
+impl CompressorOptions for LzmaOptions {
+    fn config(&self, block_size: u32) -> Result<CompressorConfig> {
+        let flags = if self.extreme {
+            SQFS_COMP_FLAG::SQFS_COMP_FLAG_LZMA_EXTREME
+        } else {
+            SQFS_COMP_FLAG(0)
+        };
+        let mut config = CompressorConfig::init(&CompressorType::Lzma, block_size, flags)?;
+
+        check_range("lzma lc + lp", self.lc.saturating_add(self.lp), 0, 4)?;
+
+        config.compressor_config.level = check_range("lzma level", self.level, 0, 9)?;
+        config.compressor_config.opt.lzma.dict_size =
+            check_dict_size(self.dict_size.unwrap_or(block_size), block_size)?;
+        config.compressor_config.opt.lzma.lc = self.lc;
+        config.compressor_config.opt.lzma.lp = self.lp;
+        config.compressor_config.opt.lzma.pb = check_range("lzma pb", self.pb, 0, 4)?;
+
+        Ok(config)
+    }
 }
@@ -0,0 +1,566 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use tar::{Archive as TarArchive, Builder as TarBuilder, EntryType, Header};
+
+use crate::archive::Archive;
+use crate::builder::{device_number, DeviceType, ImageBuilder, Metadata};
+use crate::directory_reader::{TreeNode, SQFS_TREE_FILTER_FLAGS};
+use crate::ffi::SQFS_INODE_MODE;
+use crate::file::{File, SQFS_FILE_OPEN_FLAGS};
+use crate::inode::INode;
+use crate::writer::{name_bytes, WriterOptions};
+use crate::xattr_writer::XattrWriter;
+use crate::{Result, SqfsError};
+
+/// Prefix of the PAX records star and GNU tar store extended attributes in.
+const PAX_XATTR: &str = "SCHILY.xattr.";
+/// The map of a PAX 1.0 sparse file is padded to a whole tar block.
+const TAR_BLOCK_SIZE: u64 = 512;
+
+/// Writes an image to `destination` with the contents of the tar archive read from `tar`.
+///
+/// See [add_tar] for how entries are converted.
+pub fn tar_to_sqfs<R: Read, P: AsRef<Path>>(
+    tar: R,
+    destination: P,
+    options: &WriterOptions,
+) -> Result<()> {
+    let file = File::open(destination, SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_OVERWRITE)?;
+    let mut builder = ImageBuilder::new(file, options)?;
+    add_tar(&mut builder, tar)?;
+
+    builder.finish()
+}
+
+/// Adds every entry of the tar archive read from `tar` to `builder`.
+///
+/// Plain, ustar, GNU and PAX archives are understood, including long names, hard links and
+/// GNU sparse files in both the old GNU and the PAX formats. Extended attributes are taken from
+/// `SCHILY.xattr.*` records; those squashfs can't store are skipped. Global PAX headers are
+/// ignored. The archive is read once from start to end, nothing is unpacked to disk.
+pub fn add_tar<R: Read>(builder: &mut ImageBuilder, tar: R) -> Result<()> {
+    let mut archive = TarArchive::new(tar);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let header = entry.header();
+        let entry_type = header.entry_type();
+        if entry_type.is_pax_global_extensions() {
+            continue;
+        }
+
+        let mut meta = Metadata::new()
+            .mode(u16::try_from(header.mode()? & 0o7777).expect("permissions should fit in u16"))
+            .uid(to_id(header.uid()?)?)
+            .gid(to_id(header.gid()?)?)
+            .modification_time(u32::try_from(header.mtime()?).unwrap_or(u32::MAX));
+        let devno = device_number(
+            header.device_major()?.unwrap_or(0),
+            header.device_minor()?.unwrap_or(0),
+        );
+
+        let mut pax = PaxRecords::default();
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                let extension = extension?;
+                pax.add(extension.key_bytes(), extension.value_bytes())?;
+            }
+        }
+        if let Some(mtime) = pax.mtime {
+            meta = meta.modification_time(mtime);
+        }
+        for (key, value) in &pax.xattrs {
+            meta = meta.xattr(key.as_str(), value);
+        }
+
+        let path = match &pax.sparse_name {
+            Some(name) => PathBuf::from(name),
+            None => entry.path()?.into_owned(),
+        };
+        let link_name = entry.link_name()?.map(|name| name.into_owned());
+        let link_name = || {
+            link_name.clone().ok_or_else(|| {
+                SqfsError::Read(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("tar entry {} is missing its link name", path.display()),
+                ))
+            })
+        };
+
+        match entry_type {
+            // Old archives only mark directories by a trailing slash.
+            EntryType::Regular if entry.path_bytes().ends_with(b"/") => {
+                builder.add_dir(&path, &meta)?
+            }
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                match pax.sparse()? {
+                    Some(sparse) => {
+                        let segments = match sparse.segments {
+                            Some(segments) => segments,
+                            None => read_sparse_map(&mut entry)?,
+                        };
+                        let reader = SparseReader::new(&mut entry, segments, sparse.size);
+                        builder.add_file(&path, &meta, reader)?
+                    }
+                    None => builder.add_file(&path, &meta, &mut entry)?,
+                }
+            }
+            EntryType::Directory => builder.add_dir(&path, &meta)?,
+            EntryType::Symlink => builder.add_symlink(&path, &meta, link_name()?)?,
+            EntryType::Link => builder.add_hard_link(&path, link_name()?)?,
+            EntryType::Char => builder.add_device(&path, &meta, DeviceType::Character, devno)?,
+            EntryType::Block => builder.add_device(&path, &meta, DeviceType::Block, devno)?,
+            EntryType::Fifo => builder.add_fifo(&path, &meta)?,
+            other => {
+                return Err(SqfsError::Unsupported(format!(
+                    "tar entry type {:?} of {}",
+                    other,
+                    path.display()
+                )))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn to_id(id: u64) -> Result<u32> {
+    u32::try_from(id).map_err(|_| SqfsError::Unsupported(format!("owner id {}", id)))
+}
+
+/// PAX records that the tar crate doesn't apply by itself.
+#[derive(Default)]
+struct PaxRecords {
+    mtime: Option<u32>,
+    xattrs: Vec<(String, Vec<u8>)>,
+    sparse_name: Option<String>,
+    sparse_major: Option<u64>,
+    sparse_size: Option<u64>,
+    sparse_map: Option<Vec<u64>>,
+    /// Alternating offsets and sizes of the old 0.0 format, which repeats both records.
+    sparse_pairs: Vec<u64>,
+}
+
+impl PaxRecords {
+    fn add(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        let key = match std::str::from_utf8(key) {
+            Ok(key) => key,
+            Err(_) => return Ok(()),
+        };
+        let text = || {
+            std::str::from_utf8(value)
+                .map_err(|_| invalid_record(key))
+                .map(str::trim)
+        };
+        let number = || text()?.parse::<u64>().map_err(|_| invalid_record(key));
+
+        match key {
+            "mtime" => {
+                // Seconds with an optional fraction, before 1970 as a negative number.
+                let seconds = text()?.split('.').next().unwrap_or_default();
+                let seconds = seconds.parse::<i64>().map_err(|_| invalid_record(key))?;
+                self.mtime = Some(u32::try_from(seconds.max(0)).unwrap_or(u32::MAX));
+            }
+            "GNU.sparse.name" => self.sparse_name = Some(text()?.to_string()),
+            "GNU.sparse.major" => self.sparse_major = Some(number()?),
+            "GNU.sparse.size" | "GNU.sparse.realsize" => self.sparse_size = Some(number()?),
+            "GNU.sparse.map" => {
+                let map = text()?
+                    .split(',')
+                    .map(|value| value.parse::<u64>().map_err(|_| invalid_record(key)))
+                    .collect::<Result<Vec<_>>>()?;
+                self.sparse_map = Some(map);
+            }
+            "GNU.sparse.offset" | "GNU.sparse.numbytes" => self.sparse_pairs.push(number()?),
+            _ => {
+                if let Some(name) = key.strip_prefix(PAX_XATTR) {
+                    if XattrWriter::supports(&CString::new(name)?) {
+                        self.xattrs.push((name.to_string(), value.to_vec()));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Layout of a file stored in one of the PAX sparse formats, if it is.
+    ///
+    /// Without segments, the map is at the start of the data as in format 1.0.
+    fn sparse(&self) -> Result<Option<Sparse>> {
+        let size = match self.sparse_size {
+            Some(size) => size,
+            None => return Ok(None),
+        };
+
+        let segments = if self.sparse_major == Some(1) {
+            None
+        } else if let Some(map) = &self.sparse_map {
+            Some(pairs(map, "GNU.sparse.map")?)
+        } else {
+            Some(pairs(&self.sparse_pairs, "GNU.sparse.offset")?)
+        };
+
+        Ok(Some(Sparse { size, segments }))
+    }
+}
+
+struct Sparse {
+    size: u64,
+    segments: Option<Vec<(u64, u64)>>,
+}
+
+fn pairs(values: &[u64], key: &str) -> Result<Vec<(u64, u64)>> {
+    if !values.len().is_multiple_of(2) {
+        return Err(invalid_record(key));
+    }
+
+    Ok(values.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+fn invalid_record(key: &str) -> SqfsError {
+    SqfsError::Read(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid PAX record {}", key),
+    ))
+}
+
+/// Reads the map at the start of a PAX 1.0 sparse file: the number of segments followed by
+/// their offsets and sizes, each number on its own line.
+fn read_sparse_map<R: Read>(reader: &mut R) -> Result<Vec<(u64, u64)>> {
+    let mut consumed = 0;
+    let mut read_number = || -> Result<u64> {
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        loop {
+            reader.read_exact(&mut byte)?;
+            consumed += 1;
+            if byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+
+        std::str::from_utf8(&line)
+            .ok()
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| invalid_record("GNU.sparse.map"))
+    };
+
+    let count = read_number()?;
+    let mut values = Vec::new();
+    for _ in 0..count.saturating_mul(2) {
+        values.push(read_number()?);
+    }
+
+    let padding = (TAR_BLOCK_SIZE - consumed % TAR_BLOCK_SIZE) % TAR_BLOCK_SIZE;
+    io::copy(&mut reader.take(padding), &mut io::sink())?;
+
+    pairs(&values, "GNU.sparse.map")
+}
+
+/// Expands the stored segments of a sparse file back into its full contents, with zeros in
+/// between.
+struct SparseReader<R> {
+    inner: R,
+    segments: std::vec::IntoIter<(u64, u64)>,
+    /// The segment currently being read or the next one, as offset and size.
+    current: Option<(u64, u64)>,
+    position: u64,
+    size: u64,
+}
+
+impl<R: Read> SparseReader<R> {
+    fn new(inner: R, segments: Vec<(u64, u64)>, size: u64) -> Self {
+        let mut segments = segments.into_iter();
+
+        Self {
+            inner,
+            current: segments.next(),
+            segments,
+            position: 0,
+            size,
+        }
+    }
+}
+
+impl<R: Read> Read for SparseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (available, from_data) = match self.current {
+            Some((offset, size)) if self.position >= offset + size => {
+                self.current = self.segments.next();
+                return self.read(buf);
+            }
+            Some((offset, size)) if self.position >= offset => {
+                (offset + size - self.position, true)
+            }
+            Some((offset, _)) => (offset - self.position, false),
+            None => (self.size - self.position, false),
+        };
+
+        let len = usize::try_from(available)
+            .unwrap_or(usize::MAX)
+            .min(buf.len());
+        let read = if from_data {
+            let read = self.inner.read(&mut buf[..len])?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            read
+        } else {
+            buf[..len].fill(0);
+            len
+        };
+
+        self.position += u64::try_from(read).expect("usize should fit in u64");
+
+        Ok(read)
+    }
+}
+
+/// Writes `path` inside the image and everything below it to `tar` as a PAX archive.
+///
+/// Entries are named relative to the parent of `path`, so selecting `/usr/lib` results in
+/// `lib/` followed by its contents. Selecting the root writes only its contents. See
+/// [node_to_tar] for how entries are converted. Returns `tar` once the archive is complete.
+pub fn sqfs_to_tar<P: AsRef<Path>, W: Write>(archive: &Archive, path: P, tar: W) -> Result<W> {
+    let path = path.as_ref();
+    let tree = archive.directory_reader()?.get_full_hierarchy(
+        archive.id_table()?,
+        Some(path),
+        SQFS_TREE_FILTER_FLAGS(0),
+    )?;
+    let name = path.file_name().map(PathBuf::from).unwrap_or_default();
+
+    write_tar(archive, tree.root(), name, tar)
+}
+
+/// Writes `node` of a [DirectoryTree](crate::directory_reader::DirectoryTree) read from
+/// `archive` and everything below it to `tar` as a PAX archive, named after `node`.
+///
+/// A node without a name, such as the root, is left out and only its contents are written.
+/// Extended attributes become `SCHILY.xattr.*` records and names too long for the ustar header
+/// become `path` and `linkpath` records. Later names of inodes with several links are written
+/// as hard links to the first. Sockets can't be stored in tar archives and are skipped.
+pub fn node_to_tar<W: Write>(archive: &Archive, node: TreeNode, tar: W) -> Result<W> {
+    write_tar(archive, node, PathBuf::from(node.name()), tar)
+}
+
+fn write_tar<W: Write>(archive: &Archive, node: TreeNode, name: PathBuf, tar: W) -> Result<W> {
+    let mut export = TarExport {
+        archive,
+        builder: TarBuilder::new(tar),
+        hard_links: HashMap::new(),
+    };
+
+    if name.as_os_str().is_empty() {
+        for child in node.children() {
+            export.write(child, PathBuf::from(child.name()))?;
+        }
+    } else {
+        export.write(node, name)?;
+    }
+
+    Ok(export.builder.into_inner()?)
+}
+
+/// Largest value the numeric fields of a ustar header hold in octal.
+const USTAR_ID_MAX: u64 = 0o7777777;
+const USTAR_SIZE_MAX: u64 = 0o77777777777;
+
+struct TarExport<'a, W: Write> {
+    archive: &'a Archive,
+    builder: TarBuilder<W>,
+    /// Where inodes with more than one link were first written to.
+    hard_links: HashMap<u32, PathBuf>,
+}
+
+impl<'a, W: Write> TarExport<'a, W> {
+    fn write(&mut self, node: TreeNode, path: PathBuf) -> Result<()> {
+        let inode = node.inode();
+        let is_directory = matches!(inode, INode::Directory(_) | INode::ExtendedDirectory(_));
+
+        let mut header = Header::new_ustar();
+        let mut records: Vec<(String, Vec<u8>)> = Vec::new();
+        header.set_mode(inode.mode().0 & 0o7777);
+        header.set_mtime(u64::from(inode.modification_time()));
+        header.set_size(0);
+        for (key, id) in [("uid", node.uid()), ("gid", node.gid())] {
+            if u64::from(id) > USTAR_ID_MAX {
+                records.push((key.to_string(), id.to_string().into_bytes()));
+            }
+        }
+        header.set_uid(u64::from(node.uid()).min(USTAR_ID_MAX));
+        header.set_gid(u64::from(node.gid()).min(USTAR_ID_MAX));
+
+        // Directories are named with a trailing slash, as tar itself does.
+        let name = if is_directory {
+            path.join("")
+        } else {
+            path.clone()
+        };
+        if header.set_path(&name).is_err() {
+            records.push(("path".to_string(), name_bytes(name.as_os_str())?));
+            set_truncated(&mut header.as_old_mut().name, &name)?;
+        }
+
+        if inode.extended_attribute_index() != crate::NO_XATTRS {
+            for (key, value) in self.archive.xattr_reader()?.get_for_inode(&inode)? {
+                let key = key.to_str().ok_or_else(|| SqfsError::OsUtf8(key.clone()))?;
+                records.push((format!("{}{}", PAX_XATTR, key), value));
+            }
+        }
+
+        if !is_directory && inode.number_of_hard_links() > 1 {
+            if let Some(original) = self.hard_links.get(&inode.inode_number()) {
+                header.set_entry_type(EntryType::Link);
+                set_link_name(&mut header, &mut records, original)?;
+
+                return self.append(&header, &records, io::empty());
+            }
+
+            self.hard_links.insert(inode.inode_number(), path.clone());
+        }
+
+        match &inode {
+            INode::Directory(_) | INode::ExtendedDirectory(_) => {
+                header.set_entry_type(EntryType::Directory);
+                self.append(&header, &records, io::empty())?;
+
+                for child in node.children() {
+                    self.write(child, path.join(child.name()))?;
+                }
+            }
+            INode::File(_) | INode::ExtendedFile(_) => {
+                let size = match &inode {
+                    INode::File(file) => u64::from(file.file_size()),
+                    INode::ExtendedFile(file) => file.file_size(),
+                    _ => unreachable!("inode is a file"),
+                };
+                if size > USTAR_SIZE_MAX {
+                    records.push(("size".to_string(), size.to_string().into_bytes()));
+                }
+                header.set_entry_type(EntryType::Regular);
+                header.set_size(size);
+
+                let reader = self.archive.file_reader(&inode)?;
+                self.append(&header, &records, reader)?;
+            }
+            INode::SymbolicLink(link) => self.symlink(header, records, link.target())?,
+            INode::ExtendedSymbolicLink(link) => self.symlink(header, records, link.target())?,
+            INode::Device(device) => {
+                self.device(header, records, &inode, device.device_number())?
+            }
+            INode::ExtendedDevice(device) => {
+                self.device(header, records, &inode, device.device_number())?
+            }
+            INode::Ipc(_) | INode::ExtendedIpc(_) => {
+                if inode.mode().0 & SQFS_INODE_MODE::SQFS_INODE_MODE_MASK.0
+                    == SQFS_INODE_MODE::SQFS_INODE_MODE_FIFO.0
+                {
+                    header.set_entry_type(EntryType::Fifo);
+                    self.append(&header, &records, io::empty())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn symlink(
+        &mut self,
+        mut header: Header,
+        mut records: Vec<(String, Vec<u8>)>,
+        target: &[u8],
+    ) -> Result<()> {
+        header.set_entry_type(EntryType::Symlink);
+        set_link_name(&mut header, &mut records, &path_from_bytes(target))?;
+
+        self.append(&header, &records, io::empty())
+    }
+
+    fn device(
+        &mut self,
+        mut header: Header,
+        records: Vec<(String, Vec<u8>)>,
+        inode: &INode,
+        device_number: u32,
+    ) -> Result<()> {
+        let is_block = inode.mode().0 & SQFS_INODE_MODE::SQFS_INODE_MODE_MASK.0
+            == SQFS_INODE_MODE::SQFS_INODE_MODE_BLK.0;
+        header.set_entry_type(if is_block {
+            EntryType::Block
+        } else {
+            EntryType::Char
+        });
+        header.set_device_major((device_number & 0xfff00) >> 8)?;
+        header.set_device_minor((device_number & 0xff) | ((device_number >> 12) & 0xfff00))?;
+
+        self.append(&header, &records, io::empty())
+    }
+
+    /// Writes the PAX records, if there are any, followed by the entry itself.
+    fn append<R: Read>(
+        &mut self,
+        header: &Header,
+        records: &[(String, Vec<u8>)],
+        data: R,
+    ) -> Result<()> {
+        if !records.is_empty() {
+            self.builder.append_pax_extensions(
+                records
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_slice())),
+            )?;
+        }
+
+        let mut header = header.clone();
+        header.set_cksum();
+        self.builder.append(&header, data)?;
+
+        Ok(())
+    }
+}
+
+/// Sets the link name of `header`, falling back to a `linkpath` record if it is too long.
+fn set_link_name(
+    header: &mut Header,
+    records: &mut Vec<(String, Vec<u8>)>,
+    target: &Path,
+) -> Result<()> {
+    if header.set_link_name(target).is_err() {
+        records.push(("linkpath".to_string(), name_bytes(target.as_os_str())?));
+        set_truncated(&mut header.as_old_mut().linkname, target)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).as_ref())
+}
+
+/// Stores as much of `name` as fits in a header field, for readers that ignore PAX records.
+fn set_truncated(field: &mut [u8], name: &Path) -> Result<()> {
+    let bytes = name_bytes(name.as_os_str())?;
+    let len = bytes.len().min(field.len());
+    field.fill(0);
+    field[..len].copy_from_slice(&bytes[..len]);
+
+    Ok(())
+}
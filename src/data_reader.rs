@@ -0,0 +1,279 @@
+use std::ffi::c_void;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::ptr;
+
+use crate::blocks::Block;
+use crate::compressor::Compressor;
+use crate::ffi::{
+    sqfs_data_reader_create, sqfs_data_reader_get_block, sqfs_data_reader_get_fragment,
+    sqfs_data_reader_load_fragment_table, sqfs_data_reader_read, sqfs_data_reader_t, sqfs_u8,
+};
+use crate::file::File;
+use crate::inode::{INode, OwnedINode};
+use crate::super_block::SuperBlock;
+use crate::{BlockAttributes, LibError, ManagedPointer, Result, SqfsError};
+
+/// Safe wrapper for [sqfs_data_reader_t]
+///
+/// The library keeps using the [File] and [Compressor] it was created with, so both are
+/// borrowed for as long as the reader lives.
+pub struct DataReader<'a> {
+    ptr: ManagedPointer<sqfs_data_reader_t>,
+    _borrowed: PhantomData<(&'a File, &'a Compressor)>,
+}
+
+impl<'a> DataReader<'a> {
+    /// Safe wrapper for [sqfs_data_reader_create]
+    pub fn new(
+        file: &'a File,
+        super_block: &SuperBlock,
+        compressor: &'a Compressor,
+    ) -> Result<Self> {
+        let init = || unsafe {
+            sqfs_data_reader_create(
+                file.ptr().as_ptr(),
+                usize::try_from(super_block.block_size()).expect("blocksize should fit in a usize"),
+                compressor.ptr().as_ptr(),
+                0,
+            )
+        };
+
+        ManagedPointer::check_null(&init, "Creating DataReader", crate::sqfs_destroy).map(|ptr| {
+            Self {
+                ptr,
+                _borrowed: PhantomData,
+            }
+        })
+    }
+
+    /// Safe wrapper for [sqfs_data_reader_load_fragment_table]
+    pub fn load_fragment_table(&self, super_block: &SuperBlock) -> Result<()> {
+        let code =
+            unsafe { sqfs_data_reader_load_fragment_table(self.ptr.as_ptr(), super_block.ptr()) };
+
+        crate::sqfs_check(code, "Loading fragment table into DataReader").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_data_reader_get_block]
+    pub fn get_block(&self, inode: &INode, index: usize) -> Result<Box<[u8]>> {
+        let mut size = 0usize;
+        let mut out: *mut sqfs_u8 = ptr::null_mut();
+
+        let code = unsafe {
+            sqfs_data_reader_get_block(
+                self.ptr.as_ptr(),
+                inode.ptr().as_ptr(),
+                index,
+                &mut size,
+                &mut out,
+            )
+        };
+
+        crate::sqfs_check(code, &format!("Reading block({}) of file", index))?;
+
+        Ok(take_buffer(out, size))
+    }
+
+    /// Safe wrapper for [sqfs_data_reader_get_fragment]
+    pub fn get_fragment(&self, inode: &INode) -> Result<Box<[u8]>> {
+        let mut size = 0usize;
+        let mut out: *mut sqfs_u8 = ptr::null_mut();
+
+        let code = unsafe {
+            sqfs_data_reader_get_fragment(
+                self.ptr.as_ptr(),
+                inode.ptr().as_ptr(),
+                &mut size,
+                &mut out,
+            )
+        };
+
+        crate::sqfs_check(code, "Reading fragment of file")?;
+
+        Ok(take_buffer(out, size))
+    }
+
+    /// Safe wrapper for [sqfs_data_reader_read]
+    pub fn read(&self, inode: &INode, offset: u64, buffer: &mut [u8]) -> Result<usize> {
+        let size = u32::try_from(buffer.len()).unwrap_or(u32::MAX);
+
+        let code = unsafe {
+            sqfs_data_reader_read(
+                self.ptr.as_ptr(),
+                inode.ptr().as_ptr(),
+                offset,
+                buffer.as_mut_ptr() as *mut c_void,
+                size,
+            )
+        };
+
+        crate::sqfs_check(code, "Reading file data")
+            .map(|read| usize::try_from(read).expect("positive i32 should fit in usize"))
+    }
+}
+
+/// Copies a buffer allocated by the library and frees the original.
+fn take_buffer(ptr: *mut sqfs_u8, size: usize) -> Box<[u8]> {
+    if ptr.is_null() {
+        return Box::default();
+    }
+
+    let buf = Box::from(unsafe { std::slice::from_raw_parts(ptr, size) });
+    crate::sqfs_free(ptr);
+
+    buf
+}
+
+/// [Read] and [Seek] over the contents of a [FileINode](crate::inode::FileINode) or
+/// [ExtendedFileINode](crate::inode::ExtendedFileINode).
+///
+/// Data is decompressed one block at a time. Sparse blocks are filled with zeros without
+/// touching the image and the tail end of the file is read from its fragment. The reader keeps
+/// its own copy of the inode, so it only borrows the [File] and [Compressor] it reads through.
+pub struct FileReader<'a> {
+    data_reader: DataReader<'a>,
+    inode: OwnedINode,
+    blocks: Vec<Block>,
+    block_size: u64,
+    file_size: u64,
+    has_fragment: bool,
+    position: u64,
+    current: Option<(u64, Box<[u8]>)>,
+}
+
+impl<'a> FileReader<'a> {
+    pub fn new(
+        file: &'a File,
+        super_block: &SuperBlock,
+        compressor: &'a Compressor,
+        inode: &INode,
+    ) -> Result<Self> {
+        let data_reader = DataReader::new(file, super_block, compressor)?;
+        data_reader.load_fragment_table(super_block)?;
+
+        Self::with_data_reader(data_reader, super_block, inode)
+    }
+
+    /// Creates a [FileReader] on top of an existing [DataReader].
+    ///
+    /// The [DataReader] must already have its fragment table loaded.
+    pub fn with_data_reader(
+        data_reader: DataReader<'a>,
+        super_block: &SuperBlock,
+        inode: &INode,
+    ) -> Result<Self> {
+        let (blocks, file_size, fragment_index) = match inode {
+            INode::File(file) => (
+                file.blocks().collect(),
+                u64::from(file.file_size()),
+                file.fragment_index(),
+            ),
+            INode::ExtendedFile(file) => (
+                file.blocks().collect(),
+                file.file_size(),
+                file.fragment_index(),
+            ),
+            _ => {
+                return Err(SqfsError::WrongType(
+                    format!("inode {}", inode.inode_number()),
+                    format!("{:?}", inode.tipe()),
+                    "File".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            data_reader,
+            inode: inode.copy()?,
+            blocks,
+            block_size: u64::from(super_block.block_size()),
+            file_size,
+            has_fragment: fragment_index != crate::NO_FRAGMENT,
+            position: 0,
+            current: None,
+        })
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn into_data_reader(self) -> DataReader<'a> {
+        self.data_reader
+    }
+
+    fn load_block(&self, index: u64) -> Result<Box<[u8]>> {
+        let block_index = usize::try_from(index).expect("block index should fit in a usize");
+
+        match self.blocks.get(block_index) {
+            Some(block) if block.is_sparse() => {
+                let len = self
+                    .block_size
+                    .min(self.file_size - index * self.block_size);
+                let len = usize::try_from(len).expect("blocksize should fit in a usize");
+
+                Ok(vec![0u8; len].into_boxed_slice())
+            }
+            Some(_) => self
+                .data_reader
+                .get_block(&self.inode.as_inode(), block_index),
+            None if self.has_fragment => self.data_reader.get_fragment(&self.inode.as_inode()),
+            None => Err(SqfsError::LibraryError(
+                format!("Reading block({}) of file", index),
+                LibError::OutOfBounds,
+            )),
+        }
+    }
+}
+
+impl<'a> Read for FileReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.file_size {
+            return Ok(0);
+        }
+
+        let index = self.position / self.block_size;
+        let offset = usize::try_from(self.position % self.block_size)
+            .expect("blocksize should fit in a usize");
+
+        if !matches!(&self.current, Some((current, _)) if *current == index) {
+            self.current = Some((index, self.load_block(index)?));
+        }
+
+        let (_, block) = self.current.as_ref().expect("block was just loaded");
+        let available = block.get(offset..).unwrap_or_default();
+
+        if available.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("block({}) is shorter than the file claims", index),
+            ));
+        }
+
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += u64::try_from(count).expect("usize should fit in u64");
+
+        Ok(count)
+    }
+}
+
+impl<'a> Seek for FileReader<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.file_size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
@@ -1,23 +1,36 @@
-use std::ffi::{c_char, CStr};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, CStr, OsStr, OsString};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{self, Path, PathBuf};
 use std::ptr::{self, NonNull};
+use std::rc::Rc;
+
+use num_traits::FromPrimitive;
 
 use crate::compressor::Compressor;
+use crate::ffi::sqfs_dir_entry_t;
 use crate::ffi::sqfs_dir_tree_destroy;
 use crate::ffi::sqfs_tree_node_t;
-use crate::ffi::{sqfs_dir_reader_create, sqfs_dir_reader_get_full_hierarchy, sqfs_dir_reader_t};
+use crate::ffi::{
+    sqfs_dir_reader_create, sqfs_dir_reader_find, sqfs_dir_reader_get_full_hierarchy,
+    sqfs_dir_reader_get_inode, sqfs_dir_reader_get_root_inode, sqfs_dir_reader_open_dir,
+    sqfs_dir_reader_read, sqfs_dir_reader_rewind, sqfs_dir_reader_t,
+};
 pub use crate::ffi::{SQFS_DIR_READER_FLAGS, SQFS_TREE_FILTER_FLAGS};
 use crate::file::File;
 use crate::id::IdTable;
-use crate::inode::INode;
+use crate::inode::{INode, INodeType, OwnedINode};
 use crate::super_block::SuperBlock;
 use crate::ManagedPointer;
-use crate::Result;
+use crate::{LibError, Result, SqfsError};
 
 /// Safe wrapper for [sqfs_dir_reader_t]
 pub struct DirectoryReader {
     ptr: ManagedPointer<sqfs_dir_reader_t>,
+    /// Bumped every time the position of the reader changes so a [ReadDir] can tell when
+    /// someone else moved it.
+    generation: Cell<u64>,
 }
 
 fn sqfs_directory_tree_destroy(root: *mut sqfs_tree_node_t) {
@@ -41,12 +54,225 @@ impl DirectoryReader {
             )
         };
 
-        ManagedPointer::check_null(&init, "Creating DirectoryReader", crate::sqfs_destroy)
-            .map(|ptr| Self { ptr })
+        ManagedPointer::check_null(&init, "Creating DirectoryReader", crate::sqfs_destroy).map(
+            |ptr| Self {
+                ptr,
+                generation: Cell::new(0),
+            },
+        )
     }
 
     // TODO: implement other methods
 
+    /// Safe wrapper for [sqfs_dir_reader_get_root_inode]
+    pub fn root_inode(&self) -> Result<OwnedINode> {
+        let init = |ptr| unsafe { sqfs_dir_reader_get_root_inode(self.ptr.as_ptr(), ptr) };
+
+        ManagedPointer::init_ptr(&init, "Reading root inode", crate::sqfs_free).map(OwnedINode::new)
+    }
+
+    /// Resolves `path` to its inode, following symbolic links inside the image.
+    ///
+    /// Relative paths are resolved from the root. `..` at the root stays at the root.
+    pub fn lookup<P: AsRef<Path>>(&self, path: P) -> Result<OwnedINode> {
+        self.resolve(path.as_ref(), true)
+    }
+
+    /// Like [DirectoryReader::lookup] but returns the symbolic link itself if the final
+    /// component of `path` is one.
+    pub fn lookup_no_follow<P: AsRef<Path>>(&self, path: P) -> Result<OwnedINode> {
+        self.resolve(path.as_ref(), false)
+    }
+
+    fn resolve(&self, path: &Path, follow: bool) -> Result<OwnedINode> {
+        let root = self.root_inode()?;
+        let mut resolved: Vec<(OsString, OwnedINode)> = Vec::new();
+        let mut active: Vec<ActiveLink> = Vec::new();
+        let mut followed = 0;
+        let mut pending: VecDeque<Step> = steps(path).collect();
+
+        while let Some(step) = pending.pop_front() {
+            let name = match step {
+                Step::Name(name) => name,
+                Step::Parent => {
+                    resolved.pop();
+                    continue;
+                }
+                Step::LinkEnd => {
+                    active.pop();
+                    continue;
+                }
+            };
+
+            let mut current_path = PathBuf::from("/");
+            current_path.extend(resolved.iter().map(|(name, _)| name));
+            current_path.push(&name);
+
+            let directory = resolved.last().map_or(&root, |(_, inode)| inode);
+            let inode = match self.find_in(directory, &name)? {
+                Some(inode) => inode,
+                None => {
+                    return Err(match active.last() {
+                        Some(link) => {
+                            SqfsError::DanglingLink(link.path.clone(), link.target.clone())
+                        }
+                        None => SqfsError::LibraryError(
+                            format!("Looking up {}", current_path.display()),
+                            LibError::NoEntry,
+                        ),
+                    })
+                }
+            };
+
+            let target = match inode.as_inode() {
+                INode::SymbolicLink(link) => Some(crate::os_string_from_bytes(link.target())?),
+                INode::ExtendedSymbolicLink(link) => {
+                    Some(crate::os_string_from_bytes(link.target())?)
+                }
+                _ => None,
+            };
+            let is_last = pending.iter().all(|step| matches!(step, Step::LinkEnd));
+
+            match target {
+                Some(target) if follow || !is_last => {
+                    followed += 1;
+                    if followed > crate::LINK_MAX {
+                        return Err(SqfsError::LinkChain(crate::LINK_MAX));
+                    }
+
+                    let inode_number = inode.as_inode().inode_number();
+                    if active.iter().any(|link| link.inode_number == inode_number) {
+                        return Err(SqfsError::LinkLoop(current_path));
+                    }
+
+                    let target = PathBuf::from(target);
+                    if target.has_root() {
+                        resolved.clear();
+                    }
+
+                    pending.push_front(Step::LinkEnd);
+                    for step in steps(&target).rev() {
+                        pending.push_front(step);
+                    }
+
+                    active.push(ActiveLink {
+                        inode_number,
+                        path: current_path,
+                        target,
+                    });
+                }
+                _ => resolved.push((name, inode)),
+            }
+        }
+
+        Ok(resolved.pop().map_or(root, |(_, inode)| inode))
+    }
+
+    fn find_in(&self, directory: &OwnedINode, name: &OsStr) -> Result<Option<OwnedINode>> {
+        self.open_dir(&directory.as_inode())?;
+
+        if self.find(name)? {
+            self.get_inode().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Safe wrapper for [sqfs_dir_reader_open_dir]
+    pub fn open_dir(&self, inode: &INode) -> Result<()> {
+        self.advance_generation();
+
+        let code = unsafe { sqfs_dir_reader_open_dir(self.ptr.as_ptr(), inode.ptr().as_ptr(), 0) };
+
+        crate::sqfs_check(code, "Opening directory").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_dir_reader_rewind]
+    pub fn rewind(&self) -> Result<()> {
+        self.advance_generation();
+
+        let code = unsafe { sqfs_dir_reader_rewind(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Rewinding directory").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_dir_reader_find]
+    ///
+    /// Returns `false` if the open directory has no entry called `name`. Otherwise the entry
+    /// becomes the current one and its inode can be loaded with [DirectoryReader::get_inode].
+    pub fn find<S: AsRef<OsStr>>(&self, name: S) -> Result<bool> {
+        self.advance_generation();
+
+        let bytes = crate::path_to_c_str(name.as_ref());
+        let name_ptr = bytes.as_ptr() as *const c_char;
+
+        let code = unsafe { sqfs_dir_reader_find(self.ptr.as_ptr(), name_ptr) };
+
+        match crate::sqfs_check(code, "Finding directory entry") {
+            Ok(0) => Ok(true),
+            Ok(_) | Err(SqfsError::LibraryError(_, LibError::NoEntry)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Safe wrapper for [sqfs_dir_reader_get_inode]
+    ///
+    /// Loads the inode of the entry that was last read or found.
+    pub fn get_inode(&self) -> Result<OwnedINode> {
+        let init = |ptr| unsafe { sqfs_dir_reader_get_inode(self.ptr.as_ptr(), ptr) };
+
+        ManagedPointer::init_ptr(&init, "Reading inode of directory entry", crate::sqfs_free)
+            .map(OwnedINode::new)
+    }
+
+    /// Lazily iterates over the entries of the directory `inode`.
+    pub fn read_dir(&self, inode: OwnedINode) -> Result<ReadDir<'_>> {
+        self.open_dir(&inode.as_inode())?;
+
+        Ok(ReadDir {
+            reader: self,
+            directory: Rc::new(inode),
+            last: None,
+            generation: self.generation.get(),
+            finished: false,
+        })
+    }
+
+    /// Safe wrapper for [sqfs_dir_reader_read]
+    fn read(&self) -> Result<Option<(OsString, INodeType)>> {
+        self.advance_generation();
+
+        let mut out: *mut sqfs_dir_entry_t = ptr::null_mut();
+        let code = unsafe { sqfs_dir_reader_read(self.ptr.as_ptr(), &mut out) };
+
+        if crate::sqfs_check(code, "Reading directory entry")? > 0 {
+            return Ok(None);
+        }
+
+        let entry = NonNull::new(out)
+            .map(|ptr| ManagedPointer::new(ptr, crate::sqfs_free))
+            .ok_or(SqfsError::LibraryReturnError(
+                "Reading directory entry".to_string(),
+            ))?;
+        let entry = unsafe { entry.as_ref() };
+
+        // The stored size is one less than the length of the name.
+        let len = usize::from(entry.size) + 1;
+        let name = crate::os_string_from_bytes(unsafe { entry.name.as_slice(len) })?;
+        let inode_type = INodeType::from_u16(entry.type_).ok_or_else(|| {
+            SqfsError::LibraryError(
+                format!("Reading type of directory entry {:?}", name),
+                LibError::Corrupted,
+            )
+        })?;
+
+        Ok(Some((name, inode_type)))
+    }
+
+    fn advance_generation(&self) {
+        self.generation.set(self.generation.get().wrapping_add(1));
+    }
+
     /// Safe wrapper for [sqfs_dir_reader_get_full_hierarchy]
     pub fn get_full_hierarchy<P: AsRef<Path>>(
         &self,
@@ -76,6 +302,152 @@ impl DirectoryReader {
     }
 }
 
+/// Lazy [Iterator] over the entries of a single directory.
+///
+/// The iterator shares the position of its [DirectoryReader]. If the reader is used for
+/// anything else in the meantime the iterator reopens its directory and continues after the
+/// last entry it returned.
+pub struct ReadDir<'a> {
+    reader: &'a DirectoryReader,
+    directory: Rc<OwnedINode>,
+    last: Option<OsString>,
+    generation: u64,
+    finished: bool,
+}
+
+impl<'a> ReadDir<'a> {
+    /// Starts the iteration over from the first entry.
+    pub fn rewind(&mut self) -> Result<()> {
+        if self.reader.generation.get() == self.generation {
+            self.reader.rewind()?;
+        } else {
+            self.reader.open_dir(&self.directory.as_inode())?;
+        }
+
+        self.last = None;
+        self.finished = false;
+        self.generation = self.reader.generation.get();
+
+        Ok(())
+    }
+
+    fn reposition(&mut self) -> Result<()> {
+        self.reader.open_dir(&self.directory.as_inode())?;
+
+        if let Some(last) = &self.last {
+            if !self.reader.find(last)? {
+                return Err(SqfsError::LibraryError(
+                    format!("Repositioning directory after {:?}", last),
+                    LibError::NoEntry,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = Result<DirEntry<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        if self.reader.generation.get() != self.generation {
+            if let Err(err) = self.reposition() {
+                self.finished = true;
+                return Some(Err(err));
+            }
+        }
+
+        let entry = self.reader.read();
+        self.generation = self.reader.generation.get();
+
+        match entry {
+            Ok(Some((name, inode_type))) => {
+                self.last = Some(name.clone());
+
+                Some(Ok(DirEntry {
+                    reader: self.reader,
+                    directory: Rc::clone(&self.directory),
+                    name,
+                    inode_type,
+                    generation: self.generation,
+                }))
+            }
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// An entry returned by [ReadDir].
+pub struct DirEntry<'a> {
+    reader: &'a DirectoryReader,
+    directory: Rc<OwnedINode>,
+    name: OsString,
+    inode_type: INodeType,
+    generation: u64,
+}
+
+impl<'a> DirEntry<'a> {
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// The basic type of the inode as stored in the directory listing.
+    pub fn inode_type(&self) -> INodeType {
+        self.inode_type
+    }
+
+    /// Loads the inode of this entry.
+    pub fn inode(&self) -> Result<OwnedINode> {
+        if self.reader.generation.get() != self.generation {
+            self.reader.open_dir(&self.directory.as_inode())?;
+
+            if !self.reader.find(&self.name)? {
+                return Err(SqfsError::LibraryError(
+                    format!("Looking up directory entry {:?}", self.name),
+                    LibError::NoEntry,
+                ));
+            }
+        }
+
+        self.reader.get_inode()
+    }
+}
+
+/// A single step taken while resolving a path.
+enum Step {
+    Name(OsString),
+    Parent,
+    /// Marks the end of the components that came from a symbolic link target.
+    LinkEnd,
+}
+
+/// A symbolic link whose target is still being resolved.
+struct ActiveLink {
+    inode_number: u32,
+    path: PathBuf,
+    target: PathBuf,
+}
+
+fn steps(path: &Path) -> impl DoubleEndedIterator<Item = Step> + '_ {
+    path.components().filter_map(|component| match component {
+        path::Component::Normal(name) => Some(Step::Name(name.to_os_string())),
+        path::Component::ParentDir => Some(Step::Parent),
+        _ => None,
+    })
+}
+
 pub struct DirectoryTree {
     tree_node: ManagedPointer<sqfs_tree_node_t>,
 }
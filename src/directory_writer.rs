@@ -0,0 +1,134 @@
+use std::ffi::CStr;
+
+use crate::compressor::Compressor;
+pub use crate::ffi::SQFS_DIR_WRITER_CREATE_FLAGS;
+use crate::ffi::{
+    sqfs_dir_writer_add_entry, sqfs_dir_writer_begin, sqfs_dir_writer_create,
+    sqfs_dir_writer_create_inode, sqfs_dir_writer_end, sqfs_dir_writer_get_dir_reference,
+    sqfs_dir_writer_get_entry_count, sqfs_dir_writer_get_size, sqfs_dir_writer_t,
+    sqfs_dir_writer_write_export_table,
+};
+use crate::file::File;
+use crate::inode::OwnedINode;
+use crate::meta_writer::MetaWriter;
+use crate::super_block::SuperBlock;
+use crate::{ManagedPointer, Result};
+
+/// Safe wrapper for [sqfs_dir_writer_t]
+///
+/// Keeps a pointer to the [MetaWriter] it was created with, so it has to be dropped before it.
+pub struct DirectoryWriter {
+    ptr: ManagedPointer<sqfs_dir_writer_t>,
+}
+
+impl DirectoryWriter {
+    /// Safe wrapper for [sqfs_dir_writer_create]
+    pub fn new(meta_writer: &MetaWriter, flags: SQFS_DIR_WRITER_CREATE_FLAGS) -> Result<Self> {
+        let init = || unsafe { sqfs_dir_writer_create(meta_writer.ptr().as_ptr(), flags.0) };
+
+        ManagedPointer::check_null(&init, "Creating DirectoryWriter", crate::sqfs_destroy)
+            .map(|ptr| Self { ptr })
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_begin]
+    pub fn begin(&self) -> Result<()> {
+        let code = unsafe { sqfs_dir_writer_begin(self.ptr.as_ptr(), 0) };
+
+        crate::sqfs_check(code, "Beginning directory").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_add_entry]
+    ///
+    /// Entries have to be added sorted by name and `mode` has to include the file type.
+    pub fn add_entry(
+        &self,
+        name: &CStr,
+        inode_number: u32,
+        inode_ref: u64,
+        mode: u16,
+    ) -> Result<()> {
+        let code = unsafe {
+            sqfs_dir_writer_add_entry(
+                self.ptr.as_ptr(),
+                name.as_ptr(),
+                inode_number,
+                inode_ref,
+                mode,
+            )
+        };
+
+        crate::sqfs_check(code, &format!("Adding directory entry {:?}", name)).map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_end]
+    pub fn end(&self) -> Result<()> {
+        let code = unsafe { sqfs_dir_writer_end(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Ending directory").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_get_size]
+    pub fn size(&self) -> usize {
+        unsafe { sqfs_dir_writer_get_size(self.ptr.as_ptr()) }
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_get_dir_reference]
+    pub fn reference(&self) -> u64 {
+        unsafe { sqfs_dir_writer_get_dir_reference(self.ptr.as_ptr()) }
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_get_entry_count]
+    pub fn entry_count(&self) -> usize {
+        unsafe { sqfs_dir_writer_get_entry_count(self.ptr.as_ptr()) }
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_create_inode]
+    ///
+    /// Creates the inode of the directory that was ended last. Only the type specific fields
+    /// are filled in.
+    pub fn create_inode(
+        &self,
+        hard_links: usize,
+        xattr_index: u32,
+        parent_inode_number: u32,
+    ) -> Result<OwnedINode> {
+        let init = || unsafe {
+            sqfs_dir_writer_create_inode(
+                self.ptr.as_ptr(),
+                hard_links,
+                xattr_index,
+                parent_inode_number,
+            )
+        };
+
+        ManagedPointer::check_null(&init, "Creating directory inode", crate::sqfs_free)
+            .map(OwnedINode::new)
+    }
+
+    /// Safe wrapper for [sqfs_dir_writer_write_export_table]
+    ///
+    /// Only writers created with
+    /// [SQFS_DIR_WRITER_CREATE_FLAGS::SQFS_DIR_WRITER_CREATE_EXPORT_TABLE] keep track of the
+    /// inodes needed for it.
+    pub fn write_export_table(
+        &self,
+        file: &File,
+        compressor: &Compressor,
+        root_inode_number: u32,
+        root_inode_ref: u64,
+        super_block: &mut SuperBlock,
+    ) -> Result<()> {
+        let code = unsafe {
+            sqfs_dir_writer_write_export_table(
+                self.ptr.as_ptr(),
+                file.ptr().as_ptr(),
+                compressor.ptr().as_ptr(),
+                root_inode_number,
+                root_inode_ref,
+                super_block.ptr_mut(),
+            )
+        };
+
+        crate::sqfs_check(code, "Writing export table to file").map(|_| ())
+    }
+}
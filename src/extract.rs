@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::archive::Archive;
+use crate::compressor::Compressor;
+use crate::data_reader::{DataReader, FileReader};
+use crate::directory_reader::{TreeNode, SQFS_TREE_FILTER_FLAGS};
+use crate::file::File;
+use crate::inode::{INode, OwnedINode};
+use crate::super_block::SuperBlock;
+use crate::{Result, SqfsError};
+
+/// What to do when an entry being extracted already exists in the destination.
+///
+/// Existing directories are always merged with directories from the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overwrite {
+    /// Fail with [SqfsError::Extract].
+    Never,
+    /// Leave the existing entry alone.
+    Skip,
+    /// Remove the existing entry and extract over it.
+    Replace,
+}
+
+/// Options for writing the contents of an [Archive] to a local directory.
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    overwrite: Overwrite,
+    preserve_ownership: bool,
+    skip_special_files: bool,
+    xattrs: bool,
+    secure: bool,
+    threads: usize,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            overwrite: Overwrite::Never,
+            preserve_ownership: false,
+            skip_special_files: false,
+            xattrs: true,
+            secure: true,
+            threads: 1,
+        }
+    }
+}
+
+impl ExtractOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Defaults to [Overwrite::Never].
+    pub fn overwrite(mut self, overwrite: Overwrite) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Apply the uid and gid stored in the image. Usually requires root.
+    pub fn preserve_ownership(mut self, preserve: bool) -> Self {
+        self.preserve_ownership = preserve;
+        self
+    }
+
+    /// Do not create devices, FIFOs or sockets.
+    pub fn skip_special_files(mut self, skip: bool) -> Self {
+        self.skip_special_files = skip;
+        self
+    }
+
+    /// Apply extended attributes stored in the image. Defaults to `true`.
+    ///
+    /// Attributes the destination refuses, such as `trusted.*` without root or any on a
+    /// filesystem that doesn't support them, are skipped.
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Refuse entry names that are empty, `.`, `..` or contain a `/` and never follow symbolic
+    /// links that already exist in the destination. Defaults to `true`.
+    ///
+    /// Every entry is created relative to an open descriptor of its parent directory. Turning
+    /// this off lets a crafted image write outside of the destination through `..` or an
+    /// existing symbolic link.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Number of threads decompressing file contents. Defaults to `1`, which extracts
+    /// everything on the calling thread.
+    ///
+    /// Each thread gets its own copy of the image's [File] and [Compressor], so the file has
+    /// to support [File::try_clone]. Images in memory can be shared as an `Arc<[u8]>`. The
+    /// directory structure is still created in order on the calling thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Extracts `path` inside the image so that it ends up at `destination`.
+    pub fn extract<S: AsRef<Path>, D: AsRef<Path>>(
+        &self,
+        archive: &Archive,
+        path: S,
+        destination: D,
+    ) -> Result<()> {
+        let tree = archive.directory_reader()?.get_full_hierarchy(
+            archive.id_table()?,
+            Some(path),
+            SQFS_TREE_FILTER_FLAGS(0),
+        )?;
+
+        self.extract_node(archive, tree.root(), destination)
+    }
+
+    /// Extracts `node` of a [DirectoryTree](crate::directory_reader::DirectoryTree) read from
+    /// `archive` so that it ends up at `destination`.
+    ///
+    /// Device nodes are only created when running as root and skipped otherwise.
+    pub fn extract_node<P: AsRef<Path>>(
+        &self,
+        archive: &Archive,
+        node: TreeNode,
+        destination: P,
+    ) -> Result<()> {
+        let destination = destination.as_ref();
+        let name = destination.file_name().ok_or_else(|| {
+            SqfsError::Extract(
+                destination.to_path_buf(),
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "destination must end in a file name",
+                ),
+            )
+        })?;
+        let parent = match destination.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let fail = |err| SqfsError::Extract(parent.to_path_buf(), err);
+
+        fs::create_dir_all(parent).map_err(fail)?;
+        let parent = fs::File::open(parent).map_err(fail)?;
+
+        let entry = Entry {
+            parent: &Rc::new(OwnedFd::from(parent)),
+            name: CString::new(name.as_bytes())?,
+            path: destination.to_path_buf(),
+        };
+
+        if self.threads <= 1 {
+            return Extraction::new(self, archive, None).extract(node, &entry);
+        }
+
+        let workers = (0..self.threads)
+            .map(|_| Worker::new(archive))
+            .collect::<Result<Vec<_>>>()?;
+        // Bounded so that the number of files held open while waiting for a worker is too.
+        let (sender, receiver) = mpsc::sync_channel(self.threads * 2);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        thread::scope(|scope| {
+            // Only the workers own the receiver, so once the last of them returned, even with an
+            // error, sending fails instead of blocking on a full channel.
+            let handles: Vec<_> = workers
+                .into_iter()
+                .map(|worker| {
+                    let receiver = Arc::clone(&receiver);
+                    scope.spawn(move || worker.run(receiver))
+                })
+                .collect();
+            drop(receiver);
+
+            // The extraction owns the sender, so the workers stop once it is done and every
+            // job is taken.
+            let extracted = Extraction::new(self, archive, Some(sender)).extract(node, &entry);
+            let written = handles.into_iter().try_for_each(|handle| {
+                handle
+                    .join()
+                    .expect("a thread panicked while extracting file contents")
+            });
+
+            written.and(extracted)
+        })
+    }
+}
+
+/// An entry in the destination, addressed relative to its parent directory.
+struct Entry<'a> {
+    parent: &'a Rc<OwnedFd>,
+    name: CString,
+    /// Only used to report errors.
+    path: PathBuf,
+}
+
+impl<'a> Entry<'a> {
+    /// A path to the entry that doesn't depend on any of its ancestors.
+    fn fd_path(&self) -> PathBuf {
+        Path::new("/proc/self/fd")
+            .join(self.parent.as_raw_fd().to_string())
+            .join(OsStr::from_bytes(self.name.to_bytes()))
+    }
+}
+
+enum Prepared {
+    Create,
+    Merge,
+    Skip,
+}
+
+struct Extraction<'a> {
+    options: &'a ExtractOptions,
+    archive: &'a Archive,
+    /// Where inodes with more than one link were first extracted to.
+    hard_links: HashMap<u32, (Rc<OwnedFd>, CString)>,
+    privileged: bool,
+    /// Hands file contents off to [Worker]s instead of writing them on this thread.
+    jobs: Option<mpsc::SyncSender<Job>>,
+}
+
+impl<'a> Extraction<'a> {
+    fn new(
+        options: &'a ExtractOptions,
+        archive: &'a Archive,
+        jobs: Option<mpsc::SyncSender<Job>>,
+    ) -> Self {
+        Self {
+            options,
+            archive,
+            hard_links: HashMap::new(),
+            privileged: unsafe { libc::geteuid() } == 0,
+            jobs,
+        }
+    }
+
+    fn extract(&mut self, node: TreeNode, entry: &Entry) -> Result<()> {
+        let inode = node.inode();
+        let fail = |err| SqfsError::Extract(entry.path.clone(), err);
+
+        let is_directory = matches!(inode, INode::Directory(_) | INode::ExtendedDirectory(_));
+        let is_special = matches!(
+            inode,
+            INode::Device(_) | INode::Ipc(_) | INode::ExtendedDevice(_) | INode::ExtendedIpc(_)
+        );
+        let is_device = matches!(inode, INode::Device(_) | INode::ExtendedDevice(_));
+
+        if (is_special && self.options.skip_special_files) || (is_device && !self.privileged) {
+            return Ok(());
+        }
+
+        let prepared = self.prepare(entry, is_directory)?;
+        if matches!(prepared, Prepared::Skip) {
+            return Ok(());
+        }
+
+        if !is_directory && inode.number_of_hard_links() > 1 {
+            if let Some((directory, original)) = self.hard_links.get(&inode.inode_number()) {
+                let code = unsafe {
+                    libc::linkat(
+                        directory.as_raw_fd(),
+                        original.as_ptr(),
+                        entry.parent.as_raw_fd(),
+                        entry.name.as_ptr(),
+                        0,
+                    )
+                };
+
+                return check_os(code).map_err(fail);
+            }
+
+            self.hard_links.insert(
+                inode.inode_number(),
+                (Rc::clone(entry.parent), entry.name.clone()),
+            );
+        }
+
+        let metadata = self.metadata(node, &inode)?;
+
+        match &inode {
+            INode::Directory(_) | INode::ExtendedDirectory(_) => {
+                if matches!(prepared, Prepared::Create) {
+                    let code = unsafe {
+                        libc::mkdirat(entry.parent.as_raw_fd(), entry.name.as_ptr(), 0o700)
+                    };
+                    check_os(code).map_err(fail)?;
+                }
+
+                let directory = Rc::new(self.open(entry, libc::O_RDONLY | libc::O_DIRECTORY)?);
+
+                for child in node.children() {
+                    let name = child.name();
+
+                    if self.options.secure && !is_safe_name(name) {
+                        return Err(SqfsError::UnsafeEntry(entry.path.clone(), name.to_string()));
+                    }
+
+                    let child_entry = Entry {
+                        parent: &directory,
+                        name: CString::new(name)?,
+                        path: entry.path.join(name),
+                    };
+
+                    self.extract(child, &child_entry)?;
+                }
+            }
+            INode::File(_) | INode::ExtendedFile(_) => {
+                let file = self.open(entry, libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL)?;
+                let mut file = fs::File::from(file);
+
+                let Some(jobs) = &self.jobs else {
+                    let mut reader = self.archive.file_reader(&inode)?;
+                    io::copy(&mut reader, &mut file).map_err(fail)?;
+
+                    return metadata.apply(Target::File(&file), &entry.path);
+                };
+
+                let job = Job {
+                    inode: inode.copy()?,
+                    file,
+                    metadata,
+                    path: entry.path.clone(),
+                };
+
+                return jobs.send(job).map_err(|_| {
+                    SqfsError::Internal("file extraction threads stopped early".to_string())
+                });
+            }
+            INode::SymbolicLink(link) => symlink(entry, link.target())?,
+            INode::ExtendedSymbolicLink(link) => symlink(entry, link.target())?,
+            INode::Device(device) => mknod(entry, &inode, device.device_number())?,
+            INode::ExtendedDevice(device) => mknod(entry, &inode, device.device_number())?,
+            INode::Ipc(_) | INode::ExtendedIpc(_) => mknod(entry, &inode, 0)?,
+        }
+
+        metadata.apply(Target::Entry(entry), &entry.path)
+    }
+
+    /// Makes room for a new entry, removing whatever is in the way if the options allow it.
+    fn prepare(&self, entry: &Entry, is_directory: bool) -> Result<Prepared> {
+        let fail = |err| SqfsError::Extract(entry.path.clone(), err);
+        let flags = if self.options.secure {
+            libc::AT_SYMLINK_NOFOLLOW
+        } else {
+            0
+        };
+
+        let mut stat = MaybeUninit::<libc::stat>::uninit();
+        let code = unsafe {
+            libc::fstatat(
+                entry.parent.as_raw_fd(),
+                entry.name.as_ptr(),
+                stat.as_mut_ptr(),
+                flags,
+            )
+        };
+
+        match check_os(code) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Prepared::Create),
+            Err(err) => return Err(fail(err)),
+        }
+
+        let stat = unsafe { stat.assume_init() };
+        let existing_is_directory = stat.st_mode & libc::S_IFMT == libc::S_IFDIR;
+
+        if is_directory && existing_is_directory {
+            return Ok(Prepared::Merge);
+        }
+
+        match self.options.overwrite {
+            Overwrite::Never => Err(fail(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "destination already exists",
+            ))),
+            Overwrite::Skip => Ok(Prepared::Skip),
+            Overwrite::Replace if existing_is_directory => {
+                fs::remove_dir_all(entry.fd_path()).map_err(fail)?;
+
+                Ok(Prepared::Create)
+            }
+            Overwrite::Replace => {
+                let code =
+                    unsafe { libc::unlinkat(entry.parent.as_raw_fd(), entry.name.as_ptr(), 0) };
+                check_os(code).map_err(fail)?;
+
+                Ok(Prepared::Create)
+            }
+        }
+    }
+
+    fn open(&self, entry: &Entry, flags: libc::c_int) -> Result<OwnedFd> {
+        let mut flags = flags | libc::O_CLOEXEC;
+        if self.options.secure {
+            flags |= libc::O_NOFOLLOW;
+        }
+
+        let fd = unsafe {
+            libc::openat(
+                entry.parent.as_raw_fd(),
+                entry.name.as_ptr(),
+                flags,
+                0o600 as libc::c_uint,
+            )
+        };
+
+        check_os(fd)
+            .map(|_| unsafe { OwnedFd::from_raw_fd(fd) })
+            .map_err(|err| SqfsError::Extract(entry.path.clone(), err))
+    }
+
+    fn metadata(&self, node: TreeNode, inode: &INode) -> Result<Metadata> {
+        let is_symlink = matches!(
+            inode,
+            INode::SymbolicLink(_) | INode::ExtendedSymbolicLink(_)
+        );
+        let xattrs = if self.options.xattrs {
+            self.archive.xattr_reader()?.get_for_inode(inode)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Metadata {
+            owner: self
+                .options
+                .preserve_ownership
+                .then(|| (node.uid(), node.gid())),
+            mode: (!is_symlink).then(|| (inode.mode().0 & 0o7777) as libc::mode_t),
+            xattrs,
+            mtime: libc::time_t::from(inode.modification_time()),
+        })
+    }
+}
+
+/// Where [Metadata] is applied to.
+#[derive(Clone, Copy)]
+enum Target<'a> {
+    Entry(&'a Entry<'a>),
+    File(&'a fs::File),
+}
+
+/// Everything applied to an entry after it was created. It is gathered up front so that it can
+/// be applied after a [Worker] wrote the contents of a file.
+struct Metadata {
+    owner: Option<(u32, u32)>,
+    /// Permissions of symbolic links can't be changed.
+    mode: Option<libc::mode_t>,
+    xattrs: Vec<(OsString, Vec<u8>)>,
+    mtime: libc::time_t,
+}
+
+impl Metadata {
+    fn apply(&self, target: Target, path: &Path) -> Result<()> {
+        let fail = |err| SqfsError::Extract(path.to_path_buf(), err);
+
+        // Ownership goes first since changing it may clear the setuid and setgid bits.
+        if let Some((uid, gid)) = self.owner {
+            let code = match target {
+                Target::Entry(entry) => unsafe {
+                    libc::fchownat(
+                        entry.parent.as_raw_fd(),
+                        entry.name.as_ptr(),
+                        uid,
+                        gid,
+                        libc::AT_SYMLINK_NOFOLLOW,
+                    )
+                },
+                Target::File(file) => unsafe { libc::fchown(file.as_raw_fd(), uid, gid) },
+            };
+            check_os(code).map_err(fail)?;
+        }
+
+        if let Some(mode) = self.mode {
+            let code = match target {
+                Target::Entry(entry) => unsafe {
+                    libc::fchmodat(entry.parent.as_raw_fd(), entry.name.as_ptr(), mode, 0)
+                },
+                Target::File(file) => unsafe { libc::fchmod(file.as_raw_fd(), mode) },
+            };
+            check_os(code).map_err(fail)?;
+        }
+
+        for (key, value) in &self.xattrs {
+            let key = CString::new(key.as_bytes())?;
+            let value_ptr = value.as_ptr() as *const libc::c_void;
+
+            let code = match target {
+                Target::Entry(entry) => {
+                    let fd_path = CString::new(entry.fd_path().as_os_str().as_bytes())?;
+                    unsafe {
+                        libc::lsetxattr(fd_path.as_ptr(), key.as_ptr(), value_ptr, value.len(), 0)
+                    }
+                }
+                Target::File(file) => unsafe {
+                    libc::fsetxattr(file.as_raw_fd(), key.as_ptr(), value_ptr, value.len(), 0)
+                },
+            };
+            match check_os(code) {
+                Ok(()) => {}
+                // Like unsquashfs, carry on without attributes the destination refuses.
+                Err(err) if is_refused_xattr(&err) => {}
+                Err(err) => return Err(SqfsError::Xattr(path.to_path_buf(), err)),
+            }
+        }
+
+        let mtime = libc::timespec {
+            tv_sec: self.mtime,
+            tv_nsec: 0,
+        };
+        let times = [mtime, mtime];
+        let code = match target {
+            Target::Entry(entry) => unsafe {
+                libc::utimensat(
+                    entry.parent.as_raw_fd(),
+                    entry.name.as_ptr(),
+                    times.as_ptr(),
+                    libc::AT_SYMLINK_NOFOLLOW,
+                )
+            },
+            Target::File(file) => unsafe { libc::futimens(file.as_raw_fd(), times.as_ptr()) },
+        };
+
+        check_os(code).map_err(fail)
+    }
+}
+
+/// The contents of a file waiting to be written by a [Worker].
+struct Job {
+    inode: OwnedINode,
+    file: fs::File,
+    metadata: Metadata,
+    path: PathBuf,
+}
+
+/// Writes file contents on its own thread. Compressors are stateful, so every worker reads the
+/// image through its own copy of the [File] and [Compressor].
+struct Worker {
+    file: File,
+    compressor: Compressor,
+    super_block: SuperBlock,
+}
+
+impl Worker {
+    fn new(archive: &Archive) -> Result<Self> {
+        Ok(Self {
+            file: archive.file().try_clone()?,
+            compressor: archive.compressor().try_clone()?,
+            super_block: archive.super_block().clone(),
+        })
+    }
+
+    fn run(self, jobs: Arc<Mutex<mpsc::Receiver<Job>>>) -> Result<()> {
+        let mut data_reader = DataReader::new(&self.file, &self.super_block, &self.compressor)?;
+        data_reader.load_fragment_table(&self.super_block)?;
+
+        loop {
+            // The lock is only held while waiting for a job, not while writing it.
+            let job = jobs.lock().expect(crate::LOCK_ERR).recv();
+            let Ok(mut job) = job else {
+                return Ok(());
+            };
+
+            let inode = job.inode.as_inode();
+            let mut reader = FileReader::with_data_reader(data_reader, &self.super_block, &inode)?;
+            io::copy(&mut reader, &mut job.file)
+                .map_err(|err| SqfsError::Extract(job.path.clone(), err))?;
+            data_reader = reader.into_data_reader();
+
+            job.metadata.apply(Target::File(&job.file), &job.path)?;
+        }
+    }
+}
+
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/')
+}
+
+fn symlink(entry: &Entry, target: &[u8]) -> Result<()> {
+    let target = CString::new(target)?;
+    let code = unsafe {
+        libc::symlinkat(
+            target.as_ptr(),
+            entry.parent.as_raw_fd(),
+            entry.name.as_ptr(),
+        )
+    };
+
+    check_os(code).map_err(|err| SqfsError::Extract(entry.path.clone(), err))
+}
+
+/// Creates a device, FIFO or socket. The type is taken from the mode of `inode`.
+fn mknod(entry: &Entry, inode: &INode, device_number: u32) -> Result<()> {
+    let code = unsafe {
+        libc::mknodat(
+            entry.parent.as_raw_fd(),
+            entry.name.as_ptr(),
+            inode.mode().0 as libc::mode_t,
+            libc::dev_t::from(device_number),
+        )
+    };
+
+    check_os(code).map_err(|err| SqfsError::Extract(entry.path.clone(), err))
+}
+
+/// Whether setting an extended attribute failed because the destination doesn't allow it, such
+/// as `trusted.*` without root or a filesystem without extended attributes.
+fn is_refused_xattr(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::EPERM | libc::ENOTSUP))
+}
+
+fn check_os(code: libc::c_int) -> io::Result<()> {
+    if code < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -1,15 +1,32 @@
-use std::ffi::{c_char, c_void};
+use std::ffi::{c_char, c_int, c_void};
+use std::io;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::Arc;
 
+use crate::blocks::Block;
+use crate::ffi::SQFS_ERROR::{
+    SQFS_ERROR_INTERNAL, SQFS_ERROR_IO, SQFS_ERROR_OUT_OF_BOUNDS, SQFS_ERROR_UNSUPPORTED,
+};
 pub use crate::ffi::SQFS_FILE_OPEN_FLAGS;
-use crate::ffi::{sqfs_file_t, sqfs_open_file};
-use crate::{ManagedPointer, Result};
+use crate::ffi::{sqfs_file_t, sqfs_object_t, sqfs_open_file, sqfs_u64};
+use crate::io::{Offset, SqfsIo};
+use crate::{BlockAttributes, ManagedPointer, Result, SqfsError};
 
 /// Safe wrapper for [sqfs_file_t].
 pub struct File {
     ptr: ManagedPointer<sqfs_file_t>,
+    /// Set for files opened with [File::mmap], along with where the image starts in it.
+    mapping: Option<(Arc<Mapping>, u64)>,
 }
 
+// The file is exclusively owned, so it can be moved to another thread as long as it isn't shared.
+unsafe impl Send for File {}
+
 impl File {
     /// Safe wrapper for [sqfs_open_file]
     pub fn open<P: AsRef<Path>>(path: P, flags: SQFS_FILE_OPEN_FLAGS) -> Result<Self> {
@@ -18,7 +35,104 @@ impl File {
         let init = || unsafe { sqfs_open_file(path_ptr, flags.0) };
 
         ManagedPointer::check_null(&init, "Opening file", crate::sqfs_destroy)
-            .map(|ptr| Self { ptr })
+            .map(|ptr| Self { ptr, mapping: None })
+    }
+
+    /// Opens the image embedded `offset` bytes into the file at `path` for reading.
+    ///
+    /// Every access is shifted by `offset` and the size is reported from there on, so all
+    /// tables of the image resolve as if it started the file.
+    pub fn open_at_offset<P: AsRef<Path>>(path: P, offset: u64) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+
+        Ok(Self::from_io(Box::new(Offset::new(file, offset))))
+    }
+
+    /// Maps the file at `path` into memory and reads the image from there.
+    ///
+    /// Reads copy straight out of the mapping and [File::mapped] can borrow from it without
+    /// copying at all. The file can't be written to.
+    #[cfg(unix)]
+    pub fn mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::mmap_at_offset(path, 0)
+    }
+
+    /// Combination of [File::mmap] and [File::open_at_offset].
+    #[cfg(unix)]
+    pub fn mmap_at_offset<P: AsRef<Path>>(path: P, offset: u64) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| {
+            SqfsError::Mmap(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "file is too large to map",
+            ))
+        })?;
+
+        let ptr = if len == 0 {
+            NonNull::dangling()
+        } else {
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    file.as_raw_fd(),
+                    0,
+                )
+            };
+
+            if ptr == libc::MAP_FAILED {
+                return Err(SqfsError::Mmap(io::Error::last_os_error()));
+            }
+
+            NonNull::new(ptr as *mut u8).expect("mmap should not map to null")
+        };
+
+        let mapping = Arc::new(Mapping { ptr, len });
+        let mut file = Self::from_io(Box::new(Offset::new(Arc::clone(&mapping), offset)));
+        file.mapping = Some((mapping, offset));
+
+        Ok(file)
+    }
+
+    /// Reads and writes the image through an [SqfsIo] implementation instead of a path.
+    pub fn from_io(io: Box<dyn SqfsIo>) -> Self {
+        Self {
+            ptr: ManagedPointer::new(IoFile::create(io), crate::sqfs_destroy),
+            mapping: None,
+        }
+    }
+
+    /// Creates an independent handle to the same file. Not every file supports this.
+    pub fn try_clone(&self) -> Result<Self> {
+        let ptr = crate::sqfs_copy(self.ptr.as_ptr())?;
+
+        Ok(Self {
+            ptr: ManagedPointer::new(ptr, crate::sqfs_destroy),
+            mapping: self.mapping.clone(),
+        })
+    }
+
+    /// Borrows `size` bytes at `offset` of a file opened with [File::mmap] without copying.
+    ///
+    /// Returns `None` for any other file or if the range is out of bounds.
+    pub fn mapped(&self, offset: u64, size: usize) -> Option<&[u8]> {
+        let (mapping, image_offset) = self.mapping.as_ref()?;
+        let start = usize::try_from(offset.checked_add(*image_offset)?).ok()?;
+
+        mapping.as_slice().get(start..start.checked_add(size)?)
+    }
+
+    /// Borrows the contents of an uncompressed [Block] of a file opened with [File::mmap].
+    ///
+    /// Returns `None` for compressed or sparse blocks and for any other file.
+    pub fn mapped_block(&self, block: &Block) -> Option<&[u8]> {
+        if block.is_compressed() || block.is_sparse() {
+            return None;
+        }
+
+        self.mapped(block.start_offset(), usize::try_from(block.size()).ok()?)
     }
 
     pub fn read_at(&self, start_offset: u64, size: usize) -> Result<Box<[u8]>> {
@@ -90,3 +204,151 @@ impl File {
         unsafe { &(*self.ptr.as_ptr()) }
     }
 }
+
+/// A read only mapping of a whole file, shared by every copy of a [File::mmap].
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The mapping is never written to and only unmapped once nothing refers to it.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr.as_ptr() as *mut c_void, self.len) };
+        }
+    }
+}
+
+impl SqfsIo for Arc<Mapping> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        crate::io::read_slice_at(self.as_slice(), offset, buffer)
+    }
+
+    fn write_at(&mut self, _offset: u64, _buffer: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory mapped files can't be written to",
+        ))
+    }
+
+    fn get_size(&self) -> u64 {
+        u64::try_from(self.len).expect("usize should fit in u64")
+    }
+
+    fn truncate(&mut self, _size: u64) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "memory mapped files can't be truncated",
+        ))
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        Some(Box::new(Arc::clone(self)))
+    }
+}
+
+/// [sqfs_file_t] forwarding every call to an [SqfsIo].
+///
+/// `base` has to be the first field so the library can use a pointer to this as a pointer to
+/// [sqfs_file_t] and [sqfs_object_t].
+#[repr(C)]
+struct IoFile {
+    base: sqfs_file_t,
+    io: Box<dyn SqfsIo>,
+}
+
+impl IoFile {
+    fn create(io: Box<dyn SqfsIo>) -> NonNull<sqfs_file_t> {
+        let file = Box::new(Self {
+            base: sqfs_file_t {
+                base: sqfs_object_t {
+                    destroy: Some(Self::destroy),
+                    copy: Some(Self::copy),
+                },
+                read_at: Some(Self::read_at),
+                write_at: Some(Self::write_at),
+                get_size: Some(Self::get_size),
+                truncate: Some(Self::truncate),
+            },
+            io,
+        });
+
+        NonNull::from(Box::leak(file)).cast()
+    }
+
+    /// Runs `f` on the [SqfsIo] behind `file` and turns the result into a library error code.
+    ///
+    /// Panics must not unwind into the library, so they are reported as internal errors.
+    unsafe fn call<F>(file: *mut sqfs_file_t, f: F) -> c_int
+    where
+        F: FnOnce(&mut dyn SqfsIo) -> io::Result<()>,
+    {
+        let file = &mut *(file as *mut Self);
+
+        match panic::catch_unwind(AssertUnwindSafe(|| f(file.io.as_mut()))) {
+            Ok(Ok(())) => 0,
+            Ok(Err(err)) => match err.kind() {
+                io::ErrorKind::UnexpectedEof => SQFS_ERROR_OUT_OF_BOUNDS,
+                io::ErrorKind::Unsupported => SQFS_ERROR_UNSUPPORTED,
+                _ => SQFS_ERROR_IO,
+            },
+            Err(_) => SQFS_ERROR_INTERNAL,
+        }
+    }
+
+    unsafe extern "C" fn destroy(instance: *mut sqfs_object_t) {
+        drop(Box::from_raw(instance as *mut Self));
+    }
+
+    unsafe extern "C" fn copy(orig: *const sqfs_object_t) -> *mut sqfs_object_t {
+        let orig = &*(orig as *const Self);
+
+        match panic::catch_unwind(AssertUnwindSafe(|| orig.io.try_clone())) {
+            Ok(Some(io)) => Self::create(io).as_ptr() as *mut sqfs_object_t,
+            _ => ptr::null_mut(),
+        }
+    }
+
+    unsafe extern "C" fn read_at(
+        file: *mut sqfs_file_t,
+        offset: sqfs_u64,
+        buffer: *mut c_void,
+        size: usize,
+    ) -> c_int {
+        let buffer = slice::from_raw_parts_mut(buffer as *mut u8, size);
+
+        Self::call(file, |io| io.read_at(offset, buffer))
+    }
+
+    unsafe extern "C" fn write_at(
+        file: *mut sqfs_file_t,
+        offset: sqfs_u64,
+        buffer: *const c_void,
+        size: usize,
+    ) -> c_int {
+        let buffer = slice::from_raw_parts(buffer as *const u8, size);
+
+        Self::call(file, |io| io.write_at(offset, buffer))
+    }
+
+    unsafe extern "C" fn get_size(file: *const sqfs_file_t) -> sqfs_u64 {
+        let file = &*(file as *const Self);
+
+        panic::catch_unwind(AssertUnwindSafe(|| file.io.get_size())).unwrap_or(0)
+    }
+
+    unsafe extern "C" fn truncate(file: *mut sqfs_file_t, size: sqfs_u64) -> c_int {
+        Self::call(file, |io| io.truncate(size))
+    }
+}
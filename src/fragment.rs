@@ -82,6 +82,10 @@ impl FragmentTable {
     pub fn fragments(&self) -> Fragments {
         Fragments::new(self)
     }
+
+    pub(crate) fn ptr(&self) -> &ManagedPointer<sqfs_frag_table_t> {
+        &self.ptr
+    }
 }
 
 /// Iterator over the fragment table.
@@ -130,6 +134,20 @@ impl Fragment {
     pub fn pad0(&self) -> u32 {
         self.fragment.pad0
     }
+
+    /// Reads and, if needed, decompresses the fragment block. The tail ends of files stored in
+    /// it are found at their offsets inside the returned data.
+    ///
+    /// The compressor must be created with
+    /// [SQFS_COMP_FLAG_UNCOMPRESS](crate::compressor::SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS).
+    pub fn read(&self, file: &File, compressor: &Compressor) -> Result<Vec<u8>> {
+        crate::blocks::read(file, compressor, self.start_offset(), self)
+    }
+
+    /// Reads the fragment block exactly as it is stored in the image.
+    pub fn read_raw(&self, file: &File) -> Result<Vec<u8>> {
+        crate::blocks::read_raw(file, self.start_offset(), self)
+    }
 }
 
 impl InternalBlockSize for Fragment {
@@ -1,5 +1,8 @@
 use crate::compressor::Compressor;
-use crate::ffi::{sqfs_id_table_create, sqfs_id_table_read, sqfs_id_table_t};
+use crate::ffi::{
+    sqfs_id_table_create, sqfs_id_table_id_to_index, sqfs_id_table_index_to_id, sqfs_id_table_read,
+    sqfs_id_table_t, sqfs_id_table_write,
+};
 use crate::file::File;
 use crate::super_block::SuperBlock;
 use crate::ManagedPointer;
@@ -19,11 +22,33 @@ impl IdTable {
             .map(|ptr| Self { ptr })
     }
 
-    // TODO: implement id_to_index
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__id__table__t.html#a234b2ddaef38b57615c15829308bc74f
+    /// Safe wrapper for [sqfs_id_table_id_to_index]
+    ///
+    /// Adds `id` to the table if it is not already present.
+    pub fn id_to_index(&self, id: u32) -> Result<u16> {
+        let init = |ptr| unsafe { sqfs_id_table_id_to_index(self.ptr.as_ptr(), id, ptr) };
 
-    // TODO: implement write
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__id__table__t.html#a99de5e09c14ffa6e9ab72c554cd99b72
+        crate::sqfs_init(&init, &format!("Converting id({}) to index", id))
+    }
+
+    /// Safe wrapper for [sqfs_id_table_write]
+    pub fn write(
+        &self,
+        file: &File,
+        super_block: &mut SuperBlock,
+        compressor: &Compressor,
+    ) -> Result<()> {
+        let code = unsafe {
+            sqfs_id_table_write(
+                self.ptr.as_ptr(),
+                file.ptr().as_ptr(),
+                super_block.ptr_mut(),
+                compressor.ptr().as_ptr(),
+            )
+        };
+
+        crate::sqfs_check(code, "Writing IdTable to file").map(|_| ())
+    }
 
     /// Safe wrapper for [sqfs_id_table_read]
     pub fn read(file: &File, super_block: &SuperBlock, compressor: &Compressor) -> Result<Self> {
@@ -43,8 +68,12 @@ impl IdTable {
         Ok(id_table)
     }
 
-    // TODO: implement index_to_id
-    // https://infraroot.at/projects/squashfs-tools-ng/doxydoc/structsqfs__id__table__t.html#aa133138a753b1405778ec515336ea28b
+    /// Safe wrapper for [sqfs_id_table_index_to_id]
+    pub fn index_to_id(&self, index: u16) -> Result<u32> {
+        let init = |ptr| unsafe { sqfs_id_table_index_to_id(self.ptr.as_ptr(), index, ptr) };
+
+        crate::sqfs_init(&init, &format!("Converting index({}) to id", index))
+    }
 
     pub(crate) fn ptr(&self) -> &ManagedPointer<sqfs_id_table_t> {
         &self.ptr
@@ -0,0 +1,401 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::compressor::{
+    Compressor, CompressorConfig, CompressorType, GzipStrategy, LzoAlgorithm, XzFilter,
+    SQFS_COMP_FLAG,
+};
+use crate::file::File;
+use crate::super_block::{SuperBlock, SuperFlags};
+use crate::Result;
+
+/// Summary of an image for triaging it, printed like `unsquashfs -s` by its [fmt::Display] impl.
+///
+/// Besides what `unsquashfs -s` shows, the table locations and the actual size of the file are
+/// printed at the end.
+#[derive(Clone)]
+pub struct ImageInfo {
+    version_major: u16,
+    version_minor: u16,
+    compressor: CompressorConfig,
+    block_size: u32,
+    modification_time: SystemTime,
+    flags: SuperFlags,
+    inode_count: u32,
+    fragment_count: u32,
+    id_count: u16,
+    xattr_id_count: u32,
+    inode_table_start: u64,
+    directory_table_start: u64,
+    fragment_table_start: u64,
+    export_table_start: u64,
+    id_table_start: u64,
+    xattr_id_table_start: u64,
+    bytes_used: u64,
+    file_size: u64,
+}
+
+impl ImageInfo {
+    /// Collects the summary of the image in `file`.
+    ///
+    /// The compressor options are taken from `compressor`, so it should have been configured with
+    /// [CompressorConfig::new]. The number of xattr ids is read from the xattr id table.
+    pub fn new(file: &File, super_block: &SuperBlock, compressor: &Compressor) -> Result<Self> {
+        let xattr_id_count = match super_block.xattr_id_table_start() {
+            crate::NO_TABLE => 0,
+            // The header holds the start of the key/value table followed by the number of ids.
+            start => {
+                let header = file.read_at(start, 12)?;
+                u32::from_le_bytes(header[8..12].try_into().expect("slice should be 4 bytes"))
+            }
+        };
+
+        Ok(Self {
+            version_major: super_block.version_major(),
+            version_minor: super_block.version_minor(),
+            compressor: compressor.get_configuration(),
+            block_size: super_block.block_size(),
+            modification_time: UNIX_EPOCH
+                + Duration::from_secs(u64::from(super_block.modification_time())),
+            flags: super_block.flags(),
+            inode_count: super_block.inode_count(),
+            fragment_count: super_block.fragment_entry_count(),
+            id_count: super_block.id_count(),
+            xattr_id_count,
+            inode_table_start: super_block.inode_table_start(),
+            directory_table_start: super_block.directory_table_start(),
+            fragment_table_start: super_block.fragment_table_start(),
+            export_table_start: super_block.export_table_start(),
+            id_table_start: super_block.id_table_start(),
+            xattr_id_table_start: super_block.xattr_id_table_start(),
+            bytes_used: super_block.bytes_used(),
+            file_size: file.get_size(),
+        })
+    }
+
+    pub fn version(&self) -> (u16, u16) {
+        (self.version_major, self.version_minor)
+    }
+    pub fn compressor_type(&self) -> CompressorType {
+        self.compressor.compressor_type()
+    }
+    pub fn compressor_config(&self) -> &CompressorConfig {
+        &self.compressor
+    }
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+    pub fn modification_time(&self) -> SystemTime {
+        self.modification_time
+    }
+    pub fn flags(&self) -> SuperFlags {
+        self.flags
+    }
+    pub fn inode_count(&self) -> u32 {
+        self.inode_count
+    }
+    pub fn fragment_count(&self) -> u32 {
+        self.fragment_count
+    }
+    pub fn id_count(&self) -> u16 {
+        self.id_count
+    }
+    pub fn xattr_id_count(&self) -> u32 {
+        self.xattr_id_count
+    }
+    pub fn inode_table_start(&self) -> u64 {
+        self.inode_table_start
+    }
+    pub fn directory_table_start(&self) -> u64 {
+        self.directory_table_start
+    }
+    pub fn id_table_start(&self) -> u64 {
+        self.id_table_start
+    }
+    /// `None` if the image has no fragment table.
+    pub fn fragment_table_start(&self) -> Option<u64> {
+        optional_table(self.fragment_table_start)
+    }
+    /// `None` if the image is not exportable.
+    pub fn export_table_start(&self) -> Option<u64> {
+        optional_table(self.export_table_start)
+    }
+    /// `None` if the image has no xattrs.
+    pub fn xattr_id_table_start(&self) -> Option<u64> {
+        optional_table(self.xattr_id_table_start)
+    }
+    pub fn bytes_used(&self) -> u64 {
+        self.bytes_used
+    }
+    /// Size of the file holding the image, usually [ImageInfo::bytes_used] padded to 4 KiB.
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    fn fmt_compressor_options(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let config = &self.compressor;
+
+        match config.compressor_type() {
+            CompressorType::GZip => {
+                writeln!(f, "\tcompression-level {}", config.level())?;
+                if let Some(window_size) = config.window_size() {
+                    writeln!(f, "\twindow-size {}", window_size)?;
+                }
+                write!(f, "\tStrategies selected:")?;
+                let strategies = config.gzip_strategies();
+                if strategies.is_empty() {
+                    write!(f, " default")?;
+                }
+                for strategy in strategies {
+                    write!(f, " {}", gzip_strategy_name(strategy))?;
+                }
+                writeln!(f)
+            }
+            CompressorType::Xz | CompressorType::Lzma => {
+                if let Some(dict_size) = config.dict_size() {
+                    writeln!(f, "\tDictionary size {}", dict_size)?;
+                }
+                let filters = config.xz_filters();
+                if !filters.is_empty() {
+                    write!(f, "\tFilters selected:")?;
+                    for filter in filters {
+                        write!(f, " {}", xz_filter_name(filter))?;
+                    }
+                    writeln!(f)?;
+                }
+                Ok(())
+            }
+            CompressorType::Lzo => {
+                let algorithm = config.lzo_algorithm();
+                if let Some(algorithm) = algorithm {
+                    writeln!(f, "\talgorithm {}", lzo_algorithm_name(algorithm))?;
+                }
+                if algorithm == Some(LzoAlgorithm::Lzo1x999) {
+                    writeln!(f, "\tcompression-level {}", config.level())?;
+                }
+                Ok(())
+            }
+            CompressorType::Lz4 => {
+                if config.flags().0 & SQFS_COMP_FLAG::SQFS_COMP_FLAG_LZ4_HC.0 != 0 {
+                    writeln!(f, "\tHigh Compression option specified (-Xhc)")?;
+                }
+                Ok(())
+            }
+            CompressorType::Zstd => writeln!(f, "\tcompression-level {}", config.level()),
+        }
+    }
+}
+
+impl fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flags = self.flags;
+        let un = |flag| if flags.contains(flag) { "un" } else { "" };
+        let not = |flag, set| {
+            if flags.contains(flag) == set {
+                ""
+            } else {
+                "not "
+            }
+        };
+
+        writeln!(
+            f,
+            "Found a valid SQUASHFS {}:{} superblock.",
+            self.version_major, self.version_minor
+        )?;
+        writeln!(
+            f,
+            "Creation or last append time {}",
+            format_time(self.modification_time)
+        )?;
+        writeln!(
+            f,
+            "Filesystem size {} bytes ({:.2} Kbytes / {:.2} Mbytes)",
+            self.bytes_used,
+            self.bytes_used as f64 / 1024.0,
+            self.bytes_used as f64 / (1024.0 * 1024.0)
+        )?;
+        writeln!(f, "Compression {}", self.compressor.compressor_type())?;
+        if flags.contains(SuperFlags::COMPRESSOR_OPTIONS) {
+            self.fmt_compressor_options(f)?;
+        }
+        writeln!(f, "Block size {}", self.block_size)?;
+        writeln!(
+            f,
+            "Filesystem is {}exportable via NFS",
+            not(SuperFlags::EXPORTABLE, true)
+        )?;
+        writeln!(
+            f,
+            "Inodes are {}compressed",
+            un(SuperFlags::UNCOMPRESSED_INODES)
+        )?;
+        writeln!(f, "Data is {}compressed", un(SuperFlags::UNCOMPRESSED_DATA))?;
+        writeln!(
+            f,
+            "Uids/Gids (Id table) are {}compressed",
+            un(SuperFlags::UNCOMPRESSED_IDS)
+        )?;
+        if flags.contains(SuperFlags::NO_FRAGMENTS) {
+            writeln!(f, "Fragments are not stored")?;
+        } else {
+            writeln!(
+                f,
+                "Fragments are {}compressed",
+                un(SuperFlags::UNCOMPRESSED_FRAGMENTS)
+            )?;
+        }
+        writeln!(
+            f,
+            "Always-use-fragments option is {}specified",
+            not(SuperFlags::ALWAYS_FRAGMENTS, true)
+        )?;
+        if flags.contains(SuperFlags::NO_XATTRS) {
+            writeln!(f, "Xattrs are not stored")?;
+        } else {
+            writeln!(
+                f,
+                "Xattrs are {}compressed",
+                un(SuperFlags::UNCOMPRESSED_XATTRS)
+            )?;
+        }
+        writeln!(
+            f,
+            "Duplicates are {}removed",
+            not(SuperFlags::DUPLICATES, true)
+        )?;
+        writeln!(f, "Number of fragments {}", self.fragment_count)?;
+        writeln!(f, "Number of inodes {}", self.inode_count)?;
+        writeln!(f, "Number of ids {}", self.id_count)?;
+        writeln!(f, "Number of xattr ids {}", self.xattr_id_count)?;
+
+        write!(f, "File size {} bytes", self.file_size)?;
+        match self.file_size.checked_sub(self.bytes_used) {
+            Some(padding) => writeln!(f, " ({} bytes of padding)", padding)?,
+            None => writeln!(
+                f,
+                " (truncated, {} bytes missing)",
+                self.bytes_used - self.file_size
+            )?,
+        }
+        writeln!(f, "Inode table start 0x{:x}", self.inode_table_start)?;
+        writeln!(
+            f,
+            "Directory table start 0x{:x}",
+            self.directory_table_start
+        )?;
+        fmt_optional_table(f, "Fragment table", self.fragment_table_start)?;
+        fmt_optional_table(f, "Export table", self.export_table_start)?;
+        writeln!(f, "Id table start 0x{:x}", self.id_table_start)?;
+        fmt_optional_table(f, "Xattr id table", self.xattr_id_table_start)
+    }
+}
+
+fn optional_table(start: u64) -> Option<u64> {
+    (start != crate::NO_TABLE).then_some(start)
+}
+
+fn fmt_optional_table(f: &mut fmt::Formatter<'_>, name: &str, start: u64) -> fmt::Result {
+    match optional_table(start) {
+        Some(start) => writeln!(f, "{} start 0x{:x}", name, start),
+        None => writeln!(f, "{} not present", name),
+    }
+}
+
+fn gzip_strategy_name(strategy: GzipStrategy) -> &'static str {
+    match strategy {
+        GzipStrategy::Default => "default",
+        GzipStrategy::Filtered => "filtered",
+        GzipStrategy::Huffman => "huffman-only",
+        GzipStrategy::Rle => "run-length-encoded",
+        GzipStrategy::Fixed => "fixed",
+    }
+}
+
+fn xz_filter_name(filter: XzFilter) -> &'static str {
+    match filter {
+        XzFilter::X86 => "x86",
+        XzFilter::PowerPc => "powerpc",
+        XzFilter::Ia64 => "ia64",
+        XzFilter::Arm => "arm",
+        XzFilter::ArmThumb => "armthumb",
+        XzFilter::Sparc => "sparc",
+    }
+}
+
+fn lzo_algorithm_name(algorithm: LzoAlgorithm) -> &'static str {
+    match algorithm {
+        LzoAlgorithm::Lzo1x1 => "lzo1x_1",
+        LzoAlgorithm::Lzo1x1_11 => "lzo1x_1_11",
+        LzoAlgorithm::Lzo1x1_12 => "lzo1x_1_12",
+        LzoAlgorithm::Lzo1x1_15 => "lzo1x_1_15",
+        LzoAlgorithm::Lzo1x999 => "lzo1x_999",
+    }
+}
+
+/// Formats `time` with `ctime`, in local time like unsquashfs.
+#[cfg(unix)]
+fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let time = libc::time_t::try_from(secs).unwrap_or(libc::time_t::MAX);
+
+    // ctime_r needs room for at least 26 bytes.
+    let mut buf = [0 as libc::c_char; 64];
+    let formatted = unsafe { libc::ctime_r(&time, buf.as_mut_ptr()) };
+    if formatted.is_null() {
+        // Only happens for years that don't fit the format.
+        return secs.to_string();
+    }
+
+    unsafe { std::ffi::CStr::from_ptr(formatted) }
+        .to_string_lossy()
+        .trim_end()
+        .to_string()
+}
+
+#[cfg(not(unix))]
+fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    format_time_utc(secs)
+}
+
+/// Formats `secs` since the epoch like `ctime`, but in UTC. Used where `ctime` isn't available.
+#[cfg(not(unix))]
+fn format_time_utc(secs: u64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let days = secs / 86400;
+    let secs_of_day = secs % 86400;
+
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{} {} {:>2} {:02}:{:02}:{:02} {} UTC",
+        WEEKDAYS[(days % 7) as usize],
+        MONTHS[(month - 1) as usize],
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        year
+    )
+}
@@ -1,11 +1,12 @@
 use std::marker::PhantomData;
 use std::mem::size_of;
 use std::ptr::{slice_from_raw_parts, NonNull};
+use std::slice;
 
-use crate::BlockAttributes;
+use crate::{BlockAttributes, LibError, ManagedPointer, Result, SqfsError};
 use derive_more::Deref;
 use num_derive::{FromPrimitive, ToPrimitive};
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::blocks::Block;
 pub use crate::ffi::SQFS_INODE_MODE;
@@ -14,12 +15,17 @@ use crate::ffi::{
     sqfs_inode_generic_t__bindgen_ty_1, sqfs_inode_ipc_ext_t, sqfs_inode_ipc_t,
     sqfs_inode_slink_ext_t, sqfs_inode_slink_t, sqfs_u32,
 };
+use crate::ffi::{
+    sqfs_inode_copy, sqfs_inode_get_xattr_index, sqfs_inode_make_extended,
+    sqfs_inode_set_xattr_index,
+};
 use crate::ffi::{sqfs_inode_dir_ext_t, sqfs_inode_dir_t, sqfs_inode_generic_t};
 use crate::ffi::{sqfs_inode_file_ext_t, sqfs_inode_get_file_block_start};
 use crate::ffi::{sqfs_inode_file_t, SQFS_INODE_TYPE};
+use crate::id::IdTable;
 
 /// Used by [INode] to identify inode type.
-#[derive(Debug, FromPrimitive, ToPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, ToPrimitive)]
 #[repr(u32)]
 pub enum INodeType {
     Directory = SQFS_INODE_TYPE::SQFS_INODE_DIR,
@@ -79,6 +85,136 @@ impl<'a> INode<'a> {
             }
         }
     }
+
+    /// Number of directory entries referring to this inode.
+    ///
+    /// Basic file inodes do not store a link count and always report one.
+    pub fn number_of_hard_links(&self) -> u32 {
+        match self {
+            INode::Directory(inode) => inode.number_of_hard_links(),
+            INode::File(_) => 1,
+            INode::SymbolicLink(inode) => inode.number_of_hard_links(),
+            INode::Device(inode) => inode.number_of_hard_links(),
+            INode::Ipc(inode) => inode.number_of_hard_links(),
+            INode::ExtendedDirectory(inode) => inode.number_of_hard_links(),
+            INode::ExtendedFile(inode) => inode.number_of_hard_links(),
+            INode::ExtendedSymbolicLink(inode) => inode.number_of_hard_links(),
+            INode::ExtendedDevice(inode) => inode.number_of_hard_links(),
+            INode::ExtendedIpc(inode) => inode.number_of_hard_links(),
+        }
+    }
+}
+
+impl<'a> std::ops::Deref for INode<'a> {
+    type Target = INodeInternal<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            INode::Directory(inode) => &inode.node,
+            INode::File(inode) => &inode.node,
+            INode::SymbolicLink(inode) => &inode.node,
+            INode::Device(inode) => &inode.node,
+            INode::Ipc(inode) => &inode.node,
+            INode::ExtendedDirectory(inode) => &inode.node,
+            INode::ExtendedFile(inode) => &inode.node,
+            INode::ExtendedSymbolicLink(inode) => &inode.node,
+            INode::ExtendedDevice(inode) => &inode.node,
+            INode::ExtendedIpc(inode) => &inode.node,
+        }
+    }
+}
+
+/// An [INode] allocated by the library that is freed when dropped.
+pub struct OwnedINode {
+    ptr: ManagedPointer<sqfs_inode_generic_t>,
+}
+
+// The inode is a single exclusively owned allocation.
+unsafe impl Send for OwnedINode {}
+
+impl OwnedINode {
+    pub(crate) fn new(ptr: ManagedPointer<sqfs_inode_generic_t>) -> Self {
+        Self { ptr }
+    }
+
+    pub fn as_inode(&self) -> INode<'_> {
+        INode::new(*self.ptr)
+    }
+
+    /// Allocates a zeroed inode of type `tipe` followed by `payload_size` bytes of payload,
+    /// the way the library allocates them.
+    pub(crate) fn alloc(tipe: INodeType, payload_size: usize) -> Result<Self> {
+        let size = size_of::<sqfs_inode_generic_t>() + payload_size;
+        let init = || unsafe { libc::calloc(1, size) as *mut sqfs_inode_generic_t };
+
+        let mut inode = ManagedPointer::check_null(&init, "Allocating inode", crate::sqfs_free)
+            .map(Self::new)?;
+
+        let payload_size = u32::try_from(payload_size).map_err(|_| {
+            SqfsError::LibraryError("Allocating inode".to_string(), LibError::Overflow)
+        })?;
+        let raw = inode.as_mut();
+        raw.base.type_ = tipe.to_u16().expect("inode type should fit in u16");
+        raw.payload_bytes_available = payload_size;
+        raw.payload_bytes_used = payload_size;
+
+        Ok(inode)
+    }
+
+    pub(crate) fn as_mut(&mut self) -> &mut sqfs_inode_generic_t {
+        unsafe { &mut (*self.ptr.as_ptr()) }
+    }
+
+    /// The payload following the inode, like the target of a symbolic link.
+    pub(crate) fn payload_mut(&mut self) -> &mut [u8] {
+        let len = usize::try_from(self.as_mut().payload_bytes_available)
+            .expect("u32 should fit in usize");
+
+        unsafe { slice::from_raw_parts_mut(self.as_mut().extra.as_mut_ptr() as *mut u8, len) }
+    }
+
+    /// Safe wrapper for [sqfs_inode_make_extended]
+    pub(crate) fn make_extended(&mut self) -> Result<()> {
+        let code = unsafe { sqfs_inode_make_extended(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Making inode extended").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_inode_set_xattr_index]
+    ///
+    /// Basic inodes are made extended unless `index` is `0xffffffff`.
+    pub(crate) fn set_xattr_index(&mut self, index: u32) -> Result<()> {
+        let code = unsafe { sqfs_inode_set_xattr_index(self.ptr.as_ptr(), index) };
+
+        crate::sqfs_check(code, "Setting xattr index of inode").map(|_| ())
+    }
+
+    /// Sets the link count, making basic file inodes extended since they have none.
+    pub(crate) fn set_number_of_hard_links(&mut self, links: u32) -> Result<()> {
+        let tipe = self.as_inode().tipe();
+        if tipe == INodeType::File && links > 1 {
+            self.make_extended()?;
+        }
+
+        let tipe = self.as_inode().tipe();
+        let data = &mut self.as_mut().data;
+        match tipe {
+            INodeType::Directory => data.dir.nlink = links,
+            INodeType::ExtendedDirectory => data.dir_ext.nlink = links,
+            INodeType::File => {}
+            INodeType::ExtendedFile => data.file_ext.nlink = links,
+            INodeType::SymbolicLink => data.slink.nlink = links,
+            INodeType::ExtendedSymbolicLink => data.slink_ext.nlink = links,
+            INodeType::BlockDevice | INodeType::CharacterDev => data.dev.nlink = links,
+            INodeType::ExtendedBlockDevice | INodeType::ExtendedCharacterDevice => {
+                data.dev_ext.nlink = links
+            }
+            INodeType::Fifo | INodeType::Socket => data.ipc.nlink = links,
+            INodeType::ExtendedFifo | INodeType::ExtendedSocket => data.ipc_ext.nlink = links,
+        }
+
+        Ok(())
+    }
 }
 
 /// Holder of the [sqfs_inode_generic_t]
@@ -100,7 +236,11 @@ impl<'a> INodeInternal<'a> {
         unsafe { &(*self.ptr.as_ptr()) }
     }
 
-    fn tipe(&self) -> INodeType {
+    pub(crate) fn ptr(&self) -> NonNull<sqfs_inode_generic_t> {
+        self.ptr
+    }
+
+    pub(crate) fn tipe(&self) -> INodeType {
         INodeType::from_u16(self.as_ref().base.type_).expect("invalid node type")
     }
 
@@ -116,6 +256,16 @@ impl<'a> INodeInternal<'a> {
         self.as_ref().base.gid_idx
     }
 
+    /// Resolves [INodeInternal::uid_index] to the owning user id.
+    pub fn uid(&self, id_table: &IdTable) -> Result<u32> {
+        id_table.index_to_id(self.uid_index())
+    }
+
+    /// Resolves [INodeInternal::gid_index] to the owning group id.
+    pub fn gid(&self, id_table: &IdTable) -> Result<u32> {
+        id_table.index_to_id(self.gid_index())
+    }
+
     pub fn modification_time(&self) -> u32 {
         self.as_ref().base.mod_time
     }
@@ -124,6 +274,21 @@ impl<'a> INodeInternal<'a> {
         self.as_ref().base.inode_number
     }
 
+    /// Index into the xattr id table or `0xffffffff` if the inode has no extended attributes.
+    pub fn extended_attribute_index(&self) -> u32 {
+        let mut index = crate::NO_XATTRS;
+        unsafe { sqfs_inode_get_xattr_index(self.ptr.as_ptr(), &mut index) };
+
+        index
+    }
+
+    /// Safe wrapper for [sqfs_inode_copy]
+    pub fn copy(&self) -> Result<OwnedINode> {
+        let init = |ptr| unsafe { sqfs_inode_copy(self.ptr.as_ptr(), ptr) };
+
+        ManagedPointer::init_ptr(&init, "Copying inode", crate::sqfs_free).map(OwnedINode::new)
+    }
+
     pub fn payload_bytes_available(&self) -> u32 {
         self.as_ref().payload_bytes_available
     }
@@ -209,6 +374,49 @@ impl<'a> ExtendedDirectoryINode<'a> {
     }
 }
 
+impl<'a> DeviceINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+    inode_data_field!(device_number, u32, devno);
+}
+
+impl<'a> ExtendedDeviceINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+    inode_data_field!(device_number, u32, devno);
+    inode_data_field!(extended_attribute_index, u32, xattr_idx);
+}
+
+impl<'a> IpcINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+}
+
+impl<'a> ExtendedIpcINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+    inode_data_field!(extended_attribute_index, u32, xattr_idx);
+}
+
+impl<'a> SymbolicLinkINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+    inode_data_field!(target_size, u32);
+
+    pub fn target(&self) -> &[u8] {
+        let len = usize::try_from(self.target_size()).expect("u32 should fit in usize");
+        unsafe { slice_from_raw_parts(self.extra().as_ptr() as *const u8, len).as_ref() }
+            .expect("always an extra array on symbolic links")
+    }
+}
+
+impl<'a> ExtendedSymbolicLinkINode<'a> {
+    inode_data_field!(number_of_hard_links, u32, nlink);
+    inode_data_field!(target_size, u32);
+    inode_data_field!(extended_attribute_index, u32, xattr_idx);
+
+    pub fn target(&self) -> &[u8] {
+        let len = usize::try_from(self.target_size()).expect("u32 should fit in usize");
+        unsafe { slice_from_raw_parts(self.extra().as_ptr() as *const u8, len).as_ref() }
+            .expect("always an extra array on symbolic links")
+    }
+}
+
 impl<'a> FileINode<'a> {
     inode_data_field!(fragment_index, u32);
     inode_data_field!(fragment_offset, u32);
@@ -0,0 +1,302 @@
+use std::fs;
+use std::io::{self, Cursor};
+use std::sync::Arc;
+
+/// Storage that an image can be read from and written to through
+/// [File::from_io](crate::file::File::from_io).
+///
+/// Offsets are absolute and independent of any cursor position the storage might have.
+pub trait SqfsIo: Send {
+    /// Fills all of `buffer` with the bytes starting at `offset`.
+    ///
+    /// Reading past the end should fail with [io::ErrorKind::UnexpectedEof].
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()>;
+
+    /// Writes all of `buffer` starting at `offset`, growing the storage if needed.
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()>;
+
+    fn get_size(&self) -> u64;
+
+    /// Grows or shrinks the storage to exactly `size` bytes.
+    fn truncate(&mut self, size: u64) -> io::Result<()>;
+
+    /// Creates an independent handle to the same data, used by
+    /// [File::try_clone](crate::file::File::try_clone). Not supported by default.
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        None
+    }
+}
+
+/// Shifts every access to `inner` by a fixed offset, for images embedded in a larger file.
+#[derive(Debug, Clone)]
+pub struct Offset<I> {
+    inner: I,
+    offset: u64,
+}
+
+impl<I> Offset<I> {
+    pub fn new(inner: I, offset: u64) -> Self {
+        Self { inner, offset }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+
+    fn shift(&self, offset: u64) -> io::Result<u64> {
+        offset
+            .checked_add(self.offset)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "offset overflows"))
+    }
+}
+
+impl<I: SqfsIo> SqfsIo for Offset<I> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let offset = self.shift(offset)?;
+
+        self.inner.read_at(offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()> {
+        let offset = self.shift(offset)?;
+
+        self.inner.write_at(offset, buffer)
+    }
+
+    fn get_size(&self) -> u64 {
+        self.inner.get_size().saturating_sub(self.offset)
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        let size = self.shift(size)?;
+
+        self.inner.truncate(size)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        let inner = self.inner.try_clone()?;
+
+        Some(Box::new(Offset::new(inner, self.offset)))
+    }
+}
+
+impl SqfsIo for Box<dyn SqfsIo> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        self.as_mut().read_at(offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()> {
+        self.as_mut().write_at(offset, buffer)
+    }
+
+    fn get_size(&self) -> u64 {
+        self.as_ref().get_size()
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.as_mut().truncate(size)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        self.as_ref().try_clone()
+    }
+}
+
+fn out_of_bounds() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "read past the end of the image",
+    )
+}
+
+fn to_usize(value: u64) -> io::Result<usize> {
+    usize::try_from(value)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "offset does not fit in memory"))
+}
+
+/// Reads from a byte slice, see [SqfsIo::read_at].
+pub(crate) fn read_slice_at(data: &[u8], offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    let start = to_usize(offset)?;
+    let src = start
+        .checked_add(buffer.len())
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(out_of_bounds)?;
+
+    buffer.copy_from_slice(src);
+
+    Ok(())
+}
+
+impl SqfsIo for Vec<u8> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        read_slice_at(self, offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()> {
+        let start = to_usize(offset)?;
+        let end = start
+            .checked_add(buffer.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "write overflows"))?;
+
+        if self.len() < end {
+            self.resize(end, 0);
+        }
+        self[start..end].copy_from_slice(buffer);
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> u64 {
+        u64::try_from(self.len()).expect("usize should fit in u64")
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.resize(to_usize(size)?, 0);
+
+        Ok(())
+    }
+}
+
+/// Reads and writes the underlying [Vec]. The position of the cursor is left alone.
+impl SqfsIo for Cursor<Vec<u8>> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        self.get_mut().read_at(offset, buffer)
+    }
+
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()> {
+        self.get_mut().write_at(offset, buffer)
+    }
+
+    fn get_size(&self) -> u64 {
+        self.get_ref().get_size()
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        SqfsIo::truncate(self.get_mut(), size)
+    }
+}
+
+/// Read only. The position of the cursor is left alone.
+impl SqfsIo for Cursor<&'static [u8]> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        read_slice_at(self.get_ref(), offset, buffer)
+    }
+
+    fn write_at(&mut self, _offset: u64, _buffer: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "slices can't be written to",
+        ))
+    }
+
+    fn get_size(&self) -> u64 {
+        u64::try_from(self.get_ref().len()).expect("usize should fit in u64")
+    }
+
+    fn truncate(&mut self, _size: u64) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "slices can't be truncated",
+        ))
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+/// Read only, for images shared between threads without copying them.
+impl SqfsIo for Arc<[u8]> {
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        read_slice_at(self, offset, buffer)
+    }
+
+    fn write_at(&mut self, _offset: u64, _buffer: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "shared images can't be written to",
+        ))
+    }
+
+    fn get_size(&self) -> u64 {
+        u64::try_from(self.len()).expect("usize should fit in u64")
+    }
+
+    fn truncate(&mut self, _size: u64) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "shared images can't be truncated",
+        ))
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        Some(Box::new(Arc::clone(self)))
+    }
+}
+
+/// Uses positional reads and writes, so the seek position of the file is left alone on unix.
+impl SqfsIo for fs::File {
+    #[cfg(unix)]
+    fn read_at(&mut self, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buffer, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&mut self, offset: u64, mut buffer: &mut [u8]) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        let mut offset = offset;
+        while !buffer.is_empty() {
+            match self.seek_read(buffer, offset)? {
+                0 => return Err(out_of_bounds()),
+                read => {
+                    buffer = &mut buffer[read..];
+                    offset += u64::try_from(read).expect("usize should fit in u64");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn write_at(&mut self, offset: u64, buffer: &[u8]) -> io::Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, buffer, offset)
+    }
+
+    #[cfg(windows)]
+    fn write_at(&mut self, offset: u64, mut buffer: &[u8]) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        let mut offset = offset;
+        while !buffer.is_empty() {
+            match self.seek_write(buffer, offset)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                written => {
+                    buffer = &buffer[written..];
+                    offset += u64::try_from(written).expect("usize should fit in u64");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_size(&self) -> u64 {
+        self.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+    }
+
+    fn truncate(&mut self, size: u64) -> io::Result<()> {
+        self.set_len(size)
+    }
+
+    fn try_clone(&self) -> Option<Box<dyn SqfsIo>> {
+        fs::File::try_clone(self)
+            .ok()
+            .map(|file| Box::new(file) as Box<dyn SqfsIo>)
+    }
+}
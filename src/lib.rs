@@ -8,18 +8,36 @@ use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 use thiserror::Error;
 
+pub use archive::{Archive, ArchiveOptions};
 use ffi::sqfs_object_t;
 use ffi::SQFS_ERROR::*;
 pub use libsquashfs1_sys::ffi;
 
+pub mod archive;
+pub mod block_processor;
+pub mod block_writer;
 pub mod blocks;
+pub mod builder;
 pub mod compressor;
+pub mod convert;
+pub mod data_reader;
 pub mod directory_reader;
+pub mod directory_writer;
+#[cfg(target_os = "linux")]
+pub mod extract;
 pub mod file;
 pub mod fragment;
 pub mod id;
+pub mod info;
 pub mod inode;
+pub mod io;
+pub mod meta_writer;
+pub mod pack;
+pub mod probe;
 pub mod super_block;
+pub mod writer;
+pub mod xattr_reader;
+pub mod xattr_writer;
 
 type BoxedError = Box<dyn std::error::Error + std::marker::Send + std::marker::Sync>;
 
@@ -304,8 +322,20 @@ pub enum SqfsError {
     WrappedError(BoxedError),
     #[error("Failed to retrieve xattrs for {0}: {1}")]
     Xattr(PathBuf, std::io::Error),
+    #[error("Failed to extract {0}: {1}")]
+    Extract(PathBuf, std::io::Error),
+    #[error("Invalid compressor option: {0}")]
+    CompressorOption(String),
+    #[error("Refusing to extract entry {1:?} into {0}")]
+    UnsafeEntry(PathBuf, String),
     #[error("Tried to add files to a writer that was already finished")]
     Finished,
+    #[error("{0} already exists in the image")]
+    Exists(PathBuf),
+    #[error("{0} does not exist in the image")]
+    NotFound(PathBuf),
+    #[error("Pack file line {0}: {1}")]
+    Pack(usize, String),
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -313,6 +343,15 @@ pub enum SqfsError {
 /// Result type returned by SquashFS library operations.
 pub type Result<T> = std::result::Result<T, SqfsError>;
 
+impl From<SqfsError> for std::io::Error {
+    fn from(err: SqfsError) -> Self {
+        match err {
+            SqfsError::Read(err) => err,
+            err => std::io::Error::other(err),
+        }
+    }
+}
+
 fn sqfs_check(code: i32, desc: &str) -> Result<i32> {
     match code {
         i if i >= 0 => Ok(i),
@@ -332,7 +371,21 @@ fn sqfs_destroy<T>(x: *mut T) {
     }
 }
 
+/// Copies a library object through its `copy` callback.
+fn sqfs_copy<T>(x: *const T) -> Result<NonNull<T>> {
+    let copy = unsafe { (*(x as *const sqfs_object_t)).copy }.ok_or(SqfsError::Copy)?;
+
+    NonNull::new(unsafe { copy(x as *const sqfs_object_t) } as *mut T).ok_or(SqfsError::Copy)
+}
+
+fn sqfs_free<T>(x: *mut T) {
+    unsafe { ffi::sqfs_free(x as *mut std::ffi::c_void) };
+}
+
 const NO_XATTRS: u32 = 0xffffffff;
+const NO_FRAGMENT: u32 = 0xffffffff;
+/// Marks optional tables that are not present in the image.
+const NO_TABLE: u64 = 0xffffffffffffffff;
 const LOCK_ERR: &str = "A thread panicked while holding a lock";
 // Because poisoned locks only happen when a thread panics, we probably want to panic too.
 const LINK_MAX: i32 = 1000;
@@ -432,6 +485,19 @@ fn path_to_c_str<P: AsRef<Path>>(path: P) -> Box<[u8]> {
     buf.into_boxed_slice()
 }
 
+fn os_string_from_bytes(bytes: &[u8]) -> Result<OsString> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Ok(std::ffi::OsStr::from_bytes(bytes).to_os_string())
+    }
+
+    #[cfg(windows)]
+    {
+        Ok(OsString::from(String::from_utf8(bytes.to_vec())?))
+    }
+}
+
 trait InternalBlockSize {
     fn internal_size(&self) -> u32;
 }
@@ -0,0 +1,97 @@
+use std::ffi::c_void;
+
+use crate::compressor::Compressor;
+pub use crate::ffi::SQFS_META_WRITER_FLAGS;
+use crate::ffi::{
+    sqfs_meta_write_write_to_file, sqfs_meta_writer_append, sqfs_meta_writer_create,
+    sqfs_meta_writer_flush, sqfs_meta_writer_get_position, sqfs_meta_writer_t,
+    sqfs_meta_writer_write_inode,
+};
+use crate::file::File;
+use crate::inode::INode;
+use crate::{ManagedPointer, Result};
+
+/// Safe wrapper for [sqfs_meta_writer_t]
+///
+/// Keeps pointers to the [File] and [Compressor] it was created with, so it has to be dropped
+/// before them.
+pub struct MetaWriter {
+    ptr: ManagedPointer<sqfs_meta_writer_t>,
+}
+
+impl MetaWriter {
+    /// Safe wrapper for [sqfs_meta_writer_create]
+    pub fn new(
+        file: &File,
+        compressor: &Compressor,
+        flags: SQFS_META_WRITER_FLAGS,
+    ) -> Result<Self> {
+        let init = || unsafe {
+            sqfs_meta_writer_create(file.ptr().as_ptr(), compressor.ptr().as_ptr(), flags.0)
+        };
+
+        ManagedPointer::check_null(&init, "Creating MetaWriter", crate::sqfs_destroy)
+            .map(|ptr| Self { ptr })
+    }
+
+    /// Safe wrapper for [sqfs_meta_writer_flush]
+    pub fn flush(&self) -> Result<()> {
+        let code = unsafe { sqfs_meta_writer_flush(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Flushing MetaWriter").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_meta_writer_append]
+    pub fn append(&self, data: &[u8]) -> Result<()> {
+        let code = unsafe {
+            sqfs_meta_writer_append(
+                self.ptr.as_ptr(),
+                data.as_ptr() as *const c_void,
+                data.len(),
+            )
+        };
+
+        crate::sqfs_check(code, "Appending to MetaWriter").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_meta_writer_get_position]
+    ///
+    /// Returns the start of the current block relative to the start of the table and the
+    /// offset into the uncompressed block.
+    pub fn position(&self) -> (u64, u32) {
+        let mut block_start = 0;
+        let mut offset = 0;
+
+        unsafe { sqfs_meta_writer_get_position(self.ptr.as_ptr(), &mut block_start, &mut offset) };
+
+        (block_start, offset)
+    }
+
+    /// Reference to whatever is written next, in the form used for inode references.
+    pub fn reference(&self) -> u64 {
+        let (block_start, offset) = self.position();
+
+        (block_start << 16) | u64::from(offset)
+    }
+
+    /// Safe wrapper for [sqfs_meta_write_write_to_file]
+    ///
+    /// Only writers created with [SQFS_META_WRITER_FLAGS::SQFS_META_WRITER_KEEP_IN_MEMORY] hold
+    /// anything back.
+    pub fn write_to_file(&self) -> Result<()> {
+        let code = unsafe { sqfs_meta_write_write_to_file(self.ptr.as_ptr()) };
+
+        crate::sqfs_check(code, "Writing MetaWriter to file").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_meta_writer_write_inode]
+    pub fn write_inode(&self, inode: &INode) -> Result<()> {
+        let code = unsafe { sqfs_meta_writer_write_inode(self.ptr.as_ptr(), inode.ptr().as_ptr()) };
+
+        crate::sqfs_check(code, "Writing inode to MetaWriter").map(|_| ())
+    }
+
+    pub(crate) fn ptr(&self) -> &ManagedPointer<sqfs_meta_writer_t> {
+        &self.ptr
+    }
+}
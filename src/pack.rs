@@ -0,0 +1,539 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+
+use crate::builder::{device_number, DeviceType, ImageBuilder, Metadata};
+use crate::{Result, SqfsError};
+
+/// One line of a [PackFile].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackEntry {
+    /// `dir <path> <mode> <uid> <gid>`
+    Directory { path: PathBuf, meta: Metadata },
+    /// `file <path> <mode> <uid> <gid> [<location>]`
+    ///
+    /// Without a location the contents are read from `path` inside the source directory.
+    File {
+        path: PathBuf,
+        meta: Metadata,
+        location: Option<PathBuf>,
+    },
+    /// `slink <path> <mode> <uid> <gid> <target>`
+    Symlink {
+        path: PathBuf,
+        meta: Metadata,
+        target: PathBuf,
+    },
+    /// `link <path> <dummy> <dummy> <dummy> <target>`
+    HardLink { path: PathBuf, target: PathBuf },
+    /// `nod <path> <mode> <uid> <gid> <c|b> <major> <minor>`
+    Device {
+        path: PathBuf,
+        meta: Metadata,
+        device_type: DeviceType,
+        major: u32,
+        minor: u32,
+    },
+    /// `pipe <path> <mode> <uid> <gid>`
+    Fifo { path: PathBuf, meta: Metadata },
+    /// `sock <path> <mode> <uid> <gid>`
+    Socket { path: PathBuf, meta: Metadata },
+    /// `glob <path> <mode|*> <uid|*> <gid|*> [<options>...] <location>`
+    ///
+    /// A `*` takes the value from each entry found on disk.
+    Glob {
+        path: PathBuf,
+        mode: Option<u16>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        options: GlobOptions,
+        location: PathBuf,
+    },
+}
+
+/// Kinds of entry a `glob` can be limited to with `-type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobType {
+    BlockDevice,
+    CharacterDevice,
+    Directory,
+    Fifo,
+    File,
+    SymbolicLink,
+    Socket,
+}
+
+/// Options of a `glob` line, as understood by gensquashfs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobOptions {
+    /// `-type <b|c|d|p|f|l|s>`
+    pub file_type: Option<GlobType>,
+    /// `-name <pattern>`, matched against the file name.
+    pub name: Option<String>,
+    /// `-path <pattern>`, matched against the path relative to the location.
+    pub path: Option<String>,
+    /// `-xdev` or `-mount`, which lists directories on other devices without descending into them.
+    pub one_file_system: bool,
+    /// `-keeptime`, which takes the modification time from disk.
+    pub keep_time: bool,
+    /// Cleared by `-nonrecursive`.
+    pub recursive: bool,
+}
+
+impl Default for GlobOptions {
+    fn default() -> Self {
+        Self {
+            file_type: None,
+            name: None,
+            path: None,
+            one_file_system: false,
+            keep_time: false,
+            recursive: true,
+        }
+    }
+}
+
+/// A listing of entries in the pack file format of gensquashfs from squashfs-tools-ng.
+///
+/// Every line describes one entry, blank lines and lines starting with `#` are ignored. Paths
+/// and locations can be quoted with `"` to include spaces, with `\"` and `\\` as escapes.
+/// Modes are octal, ids decimal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackFile {
+    entries: Vec<PackEntry>,
+}
+
+impl PackFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(BufReader::new(fs::File::open(path)?))
+    }
+
+    pub fn parse<R: BufRead>(reader: R) -> Result<Self> {
+        let mut entries = Vec::new();
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let entry = parse_line(line).map_err(|message| SqfsError::Pack(index + 1, message))?;
+            entries.push(entry);
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn entries(&self) -> &[PackEntry] {
+        &self.entries
+    }
+
+    /// Adds every entry to `builder`, in the order they are listed.
+    ///
+    /// Relative file and glob locations are looked up inside `source`. Device nodes are only
+    /// described in the image, so creating them needs no privileges.
+    pub fn build<P: AsRef<Path>>(&self, builder: &mut ImageBuilder, source: P) -> Result<()> {
+        let source = source.as_ref();
+
+        for entry in &self.entries {
+            match entry {
+                PackEntry::Directory { path, meta } => builder.add_dir(path, meta)?,
+                PackEntry::File {
+                    path,
+                    meta,
+                    location,
+                } => {
+                    let location = match location {
+                        Some(location) => source.join(location),
+                        None => source.join(relative(path)),
+                    };
+                    builder.add_file(path, meta, fs::File::open(location)?)?
+                }
+                PackEntry::Symlink { path, meta, target } => {
+                    builder.add_symlink(path, meta, target)?
+                }
+                PackEntry::HardLink { path, target } => builder.add_hard_link(path, target)?,
+                PackEntry::Device {
+                    path,
+                    meta,
+                    device_type,
+                    major,
+                    minor,
+                } => builder.add_device(path, meta, *device_type, device_number(*major, *minor))?,
+                PackEntry::Fifo { path, meta } => builder.add_fifo(path, meta)?,
+                PackEntry::Socket { path, meta } => builder.add_socket(path, meta)?,
+                PackEntry::Glob {
+                    path,
+                    mode,
+                    uid,
+                    gid,
+                    options,
+                    location,
+                } => {
+                    let glob = Glob {
+                        builder: &mut *builder,
+                        mode: *mode,
+                        uid: *uid,
+                        gid: *gid,
+                        options,
+                    };
+                    glob.add(path, &source.join(location))?
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Strips the root from a path inside the image so it can be joined onto a directory.
+fn relative(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+fn parse_line(line: &str) -> std::result::Result<PackEntry, String> {
+    let mut rest = line;
+    let keyword = next_token(&mut rest)?.unwrap_or_default();
+    let path = PathBuf::from(required(&mut rest, "path")?);
+
+    if keyword == "link" {
+        for _ in 0..3 {
+            required(&mut rest, "dummy field")?;
+        }
+
+        return Ok(PackEntry::HardLink {
+            path,
+            target: PathBuf::from(remainder(rest)?.ok_or("missing link target")?),
+        });
+    }
+
+    let mode = required(&mut rest, "mode")?;
+    let uid = required(&mut rest, "uid")?;
+    let gid = required(&mut rest, "gid")?;
+
+    if keyword == "glob" {
+        let options = parse_glob_options(&mut rest)?;
+
+        return Ok(PackEntry::Glob {
+            path,
+            mode: wildcard(&mode, parse_mode)?,
+            uid: wildcard(&uid, parse_id)?,
+            gid: wildcard(&gid, parse_id)?,
+            options,
+            location: PathBuf::from(remainder(rest)?.ok_or("missing glob location")?),
+        });
+    }
+
+    let meta = Metadata::new()
+        .mode(parse_mode(&mode)?)
+        .uid(parse_id(&uid)?)
+        .gid(parse_id(&gid)?);
+
+    let entry = match keyword.as_str() {
+        "dir" => PackEntry::Directory { path, meta },
+        "file" => PackEntry::File {
+            path,
+            meta,
+            location: remainder(rest)?.map(PathBuf::from),
+        },
+        "slink" => PackEntry::Symlink {
+            path,
+            meta,
+            target: PathBuf::from(remainder(rest)?.ok_or("missing symbolic link target")?),
+        },
+        "nod" => {
+            let device_type = match required(&mut rest, "device type")?.as_str() {
+                "c" | "C" => DeviceType::Character,
+                "b" | "B" => DeviceType::Block,
+                other => return Err(format!("unknown device type {}", other)),
+            };
+
+            PackEntry::Device {
+                path,
+                meta,
+                device_type,
+                major: parse_id(&required(&mut rest, "major number")?)?,
+                minor: parse_id(&required(&mut rest, "minor number")?)?,
+            }
+        }
+        "pipe" => PackEntry::Fifo { path, meta },
+        "sock" => PackEntry::Socket { path, meta },
+        other => return Err(format!("unknown entry type {}", other)),
+    };
+
+    Ok(entry)
+}
+
+fn parse_glob_options(rest: &mut &str) -> std::result::Result<GlobOptions, String> {
+    let mut options = GlobOptions::default();
+
+    while rest.trim_start().starts_with('-') {
+        let option = next_token(rest)?.unwrap_or_default();
+        match option.as_str() {
+            "--" => break,
+            "-xdev" | "-mount" => options.one_file_system = true,
+            "-keeptime" => options.keep_time = true,
+            "-nonrecursive" => options.recursive = false,
+            "-name" => options.name = Some(required(rest, "-name pattern")?),
+            "-path" => options.path = Some(required(rest, "-path pattern")?),
+            "-type" => {
+                let file_type = match required(rest, "-type argument")?.as_str() {
+                    "b" => GlobType::BlockDevice,
+                    "c" => GlobType::CharacterDevice,
+                    "d" => GlobType::Directory,
+                    "p" => GlobType::Fifo,
+                    "f" => GlobType::File,
+                    "l" => GlobType::SymbolicLink,
+                    "s" => GlobType::Socket,
+                    other => return Err(format!("unknown -type argument {}", other)),
+                };
+                options.file_type = Some(file_type);
+            }
+            other => return Err(format!("unknown glob option {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_mode(mode: &str) -> std::result::Result<u16, String> {
+    u16::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("invalid mode {}", mode))
+}
+
+fn parse_id(id: &str) -> std::result::Result<u32, String> {
+    id.parse().map_err(|_| format!("invalid number {}", id))
+}
+
+fn wildcard<T>(
+    value: &str,
+    parse: fn(&str) -> std::result::Result<T, String>,
+) -> std::result::Result<Option<T>, String> {
+    match value {
+        "*" => Ok(None),
+        value => parse(value).map(Some),
+    }
+}
+
+fn required(rest: &mut &str, field: &str) -> std::result::Result<String, String> {
+    next_token(rest)?.ok_or_else(|| format!("missing {}", field))
+}
+
+/// The rest of a line, which may contain spaces without being quoted.
+fn remainder(rest: &str) -> std::result::Result<Option<String>, String> {
+    let mut rest = rest.trim();
+    if !rest.starts_with('"') {
+        return Ok(Some(rest.to_string()).filter(|rest| !rest.is_empty()));
+    }
+
+    let token = next_token(&mut rest)?;
+    if !rest.trim().is_empty() {
+        return Err(format!("unexpected {} after quoted string", rest.trim()));
+    }
+
+    Ok(token)
+}
+
+/// Takes the next field off `rest`, which is either a run of non-whitespace or a quoted
+/// string.
+fn next_token(rest: &mut &str) -> std::result::Result<Option<String>, String> {
+    let trimmed = rest.trim_start();
+    if trimmed.is_empty() {
+        *rest = trimmed;
+        return Ok(None);
+    }
+
+    let Some(quoted) = trimmed.strip_prefix('"') else {
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        *rest = &trimmed[end..];
+        return Ok(Some(trimmed[..end].to_string()));
+    };
+
+    let mut token = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => {
+                *rest = &quoted[index + 1..];
+                return Ok(Some(token));
+            }
+            '\\' => match chars.next() {
+                Some((_, escaped)) => token.push(escaped),
+                None => break,
+            },
+            c => token.push(c),
+        }
+    }
+
+    Err("unterminated quoted string".to_string())
+}
+
+/// Adds the entries found on disk for a `glob` line.
+struct Glob<'a> {
+    builder: &'a mut ImageBuilder,
+    mode: Option<u16>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    options: &'a GlobOptions,
+}
+
+impl<'a> Glob<'a> {
+    #[cfg(unix)]
+    fn add(mut self, path: &Path, location: &Path) -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(location)?;
+        if !metadata.is_dir() {
+            return Err(SqfsError::WrongType(
+                location.display().to_string(),
+                "file".to_string(),
+                "directory".to_string(),
+            ));
+        }
+
+        self.add_directory(path, location, Path::new(""), metadata.dev())
+    }
+
+    #[cfg(not(unix))]
+    fn add(self, _path: &Path, _location: &Path) -> Result<()> {
+        Err(SqfsError::Unsupported("glob in pack files".to_string()))
+    }
+
+    #[cfg(unix)]
+    fn add_directory(
+        &mut self,
+        path: &Path,
+        directory: &Path,
+        relative: &Path,
+        device: u64,
+    ) -> Result<()> {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let mut entries = fs::read_dir(directory)?.collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let source = entry.path();
+            let relative = relative.join(entry.file_name());
+            let metadata = fs::symlink_metadata(&source)?;
+            let file_type = metadata.file_type();
+
+            let glob_type = if file_type.is_dir() {
+                GlobType::Directory
+            } else if file_type.is_file() {
+                GlobType::File
+            } else if file_type.is_symlink() {
+                GlobType::SymbolicLink
+            } else if file_type.is_block_device() {
+                GlobType::BlockDevice
+            } else if file_type.is_char_device() {
+                GlobType::CharacterDevice
+            } else if file_type.is_fifo() {
+                GlobType::Fifo
+            } else if file_type.is_socket() {
+                GlobType::Socket
+            } else {
+                return Err(SqfsError::WriteType(file_type));
+            };
+
+            if self.matches(glob_type, &relative)? {
+                let target = path.join(&relative);
+                let mut meta = Metadata::new()
+                    .mode(
+                        self.mode.unwrap_or(
+                            u16::try_from(metadata.mode() & 0o7777)
+                                .expect("permissions should fit in u16"),
+                        ),
+                    )
+                    .uid(self.uid.unwrap_or(metadata.uid()))
+                    .gid(self.gid.unwrap_or(metadata.gid()));
+                if self.options.keep_time {
+                    meta = meta.modification_time(
+                        u32::try_from(metadata.mtime().max(0)).unwrap_or(u32::MAX),
+                    );
+                }
+
+                let device_number = || {
+                    u32::try_from(metadata.rdev()).map_err(|_| {
+                        SqfsError::Unsupported(format!("device number {}", metadata.rdev()))
+                    })
+                };
+
+                match glob_type {
+                    GlobType::Directory => self.builder.add_dir(&target, &meta)?,
+                    GlobType::File => {
+                        self.builder
+                            .add_file(&target, &meta, fs::File::open(&source)?)?
+                    }
+                    GlobType::SymbolicLink => {
+                        self.builder
+                            .add_symlink(&target, &meta, fs::read_link(&source)?)?
+                    }
+                    GlobType::BlockDevice => self.builder.add_device(
+                        &target,
+                        &meta,
+                        DeviceType::Block,
+                        device_number()?,
+                    )?,
+                    GlobType::CharacterDevice => self.builder.add_device(
+                        &target,
+                        &meta,
+                        DeviceType::Character,
+                        device_number()?,
+                    )?,
+                    GlobType::Fifo => self.builder.add_fifo(&target, &meta)?,
+                    GlobType::Socket => self.builder.add_socket(&target, &meta)?,
+                }
+            }
+
+            // Like find, a mount point is listed but not descended into.
+            let other_device = self.options.one_file_system && metadata.dev() != device;
+            if glob_type == GlobType::Directory && self.options.recursive && !other_device {
+                self.add_directory(path, &source, &relative, device)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn matches(&self, glob_type: GlobType, relative: &Path) -> Result<bool> {
+        use std::os::unix::ffi::OsStrExt;
+
+        if self
+            .options
+            .file_type
+            .is_some_and(|file_type| file_type != glob_type)
+        {
+            return Ok(false);
+        }
+
+        let name = relative.file_name().unwrap_or_default().as_bytes();
+        if let Some(pattern) = &self.options.name {
+            if !fnmatch(pattern, name)? {
+                return Ok(false);
+            }
+        }
+        if let Some(pattern) = &self.options.path {
+            if !fnmatch(pattern, relative.as_os_str().as_bytes())? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Matches `name` against a shell wildcard `pattern` the way find(1) does.
+#[cfg(unix)]
+fn fnmatch(pattern: &str, name: &[u8]) -> Result<bool> {
+    let pattern = std::ffi::CString::new(pattern)?;
+    let name = std::ffi::CString::new(name)?;
+
+    Ok(unsafe { libc::fnmatch(pattern.as_ptr(), name.as_ptr(), 0) } == 0)
+}
@@ -0,0 +1,117 @@
+use std::mem::size_of;
+use std::path::Path;
+
+use crate::ffi::sqfs_super_t;
+use crate::file::File;
+use crate::io::SqfsIo;
+use crate::super_block::SuperBlock;
+use crate::Result;
+
+/// [SuperBlock::magic] as it is stored on disk.
+const MAGIC: &[u8; 4] = b"hsqs";
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// An image found inside a larger file by [probe].
+pub struct Candidate {
+    offset: u64,
+    super_block: SuperBlock,
+}
+
+impl Candidate {
+    /// Where the image starts, to be passed to [File::open_at_offset] or
+    /// [ArchiveOptions::offset](crate::ArchiveOptions::offset).
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn super_block(&self) -> &SuperBlock {
+        &self.super_block
+    }
+}
+
+/// Scans the file at `path` for embedded images, see [probe].
+pub fn probe_path<P: AsRef<Path>>(path: P) -> Result<Vec<Candidate>> {
+    probe(&mut std::fs::File::open(path)?)
+}
+
+/// Scans `io` for the squashfs magic and returns every hit that holds up as a [SuperBlock].
+///
+/// A hit is kept if it is version 4.0, its `block_log` matches its block size and the image
+/// along with its tables fits in the rest of `io`.
+pub fn probe(io: &mut dyn SqfsIo) -> Result<Vec<Candidate>> {
+    let size = io.get_size();
+    let mut candidates = Vec::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut start = 0u64;
+
+    while start < size {
+        let len = usize::try_from((size - start).min(CHUNK_SIZE as u64))
+            .expect("chunk size should fit in a usize");
+        let chunk = &mut buffer[..len];
+        io.read_at(start, chunk)?;
+
+        for (index, window) in chunk.windows(MAGIC.len()).enumerate() {
+            if window != MAGIC {
+                continue;
+            }
+
+            let offset = start + u64::try_from(index).expect("usize should fit in u64");
+            if let Some(super_block) = validate(io, offset, size)? {
+                candidates.push(Candidate {
+                    offset,
+                    super_block,
+                });
+            }
+        }
+
+        if start + u64::try_from(len).expect("usize should fit in u64") >= size {
+            break;
+        }
+
+        // Overlap the chunks so a magic crossing the boundary is still found.
+        start += u64::try_from(len - (MAGIC.len() - 1)).expect("usize should fit in u64");
+    }
+
+    Ok(candidates)
+}
+
+fn validate(io: &mut dyn SqfsIo, offset: u64, size: u64) -> Result<Option<SuperBlock>> {
+    let available = size - offset;
+    let mut bytes = vec![0u8; size_of::<sqfs_super_t>()];
+
+    if available < u64::try_from(bytes.len()).expect("usize should fit in u64") {
+        return Ok(None);
+    }
+    io.read_at(offset, &mut bytes)?;
+
+    // The library already rejects unknown versions, block sizes and compressors.
+    let Ok(super_block) = SuperBlock::read(&File::from_io(Box::new(bytes))) else {
+        return Ok(None);
+    };
+
+    let bytes_used = super_block.bytes_used();
+    let block_size_matches = 1u32
+        .checked_shl(u32::from(super_block.block_log()))
+        .is_some_and(|block_size| block_size == super_block.block_size());
+    let required_tables = [
+        super_block.inode_table_start(),
+        super_block.directory_table_start(),
+        super_block.id_table_start(),
+    ];
+    let optional_tables = [
+        super_block.fragment_table_start(),
+        super_block.export_table_start(),
+        super_block.xattr_id_table_start(),
+    ];
+
+    let valid = super_block.version_major() == 4
+        && super_block.version_minor() == 0
+        && block_size_matches
+        && bytes_used <= available
+        && required_tables.iter().all(|start| *start < bytes_used)
+        && optional_tables
+            .iter()
+            .all(|start| *start == crate::NO_TABLE || *start < bytes_used);
+
+    Ok(valid.then_some(super_block))
+}
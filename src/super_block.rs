@@ -1,11 +1,33 @@
-use num_traits::FromPrimitive;
+use bitflags::bitflags;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::compressor::CompressorType;
-use crate::ffi::{sqfs_super_read, sqfs_super_t, sqfs_super_write};
+use crate::ffi::{
+    sqfs_super_init, sqfs_super_read, sqfs_super_t, sqfs_super_write, SQFS_SUPER_FLAGS,
+};
 use crate::file::File;
 use crate::Result;
 
+bitflags! {
+    /// Flags stored in the super block, mirroring [SQFS_SUPER_FLAGS].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct SuperFlags: u16 {
+        const UNCOMPRESSED_INODES = SQFS_SUPER_FLAGS::SQFS_FLAG_UNCOMPRESSED_INODES.0 as u16;
+        const UNCOMPRESSED_DATA = SQFS_SUPER_FLAGS::SQFS_FLAG_UNCOMPRESSED_DATA.0 as u16;
+        const UNCOMPRESSED_FRAGMENTS = SQFS_SUPER_FLAGS::SQFS_FLAG_UNCOMPRESSED_FRAGMENTS.0 as u16;
+        const NO_FRAGMENTS = SQFS_SUPER_FLAGS::SQFS_FLAG_NO_FRAGMENTS.0 as u16;
+        const ALWAYS_FRAGMENTS = SQFS_SUPER_FLAGS::SQFS_FLAG_ALWAYS_FRAGMENTS.0 as u16;
+        const DUPLICATES = SQFS_SUPER_FLAGS::SQFS_FLAG_DUPLICATES.0 as u16;
+        const EXPORTABLE = SQFS_SUPER_FLAGS::SQFS_FLAG_EXPORTABLE.0 as u16;
+        const UNCOMPRESSED_XATTRS = SQFS_SUPER_FLAGS::SQFS_FLAG_UNCOMPRESSED_XATTRS.0 as u16;
+        const NO_XATTRS = SQFS_SUPER_FLAGS::SQFS_FLAG_NO_XATTRS.0 as u16;
+        const COMPRESSOR_OPTIONS = SQFS_SUPER_FLAGS::SQFS_FLAG_COMPRESSOR_OPTIONS.0 as u16;
+        const UNCOMPRESSED_IDS = SQFS_SUPER_FLAGS::SQFS_FLAG_UNCOMPRESSED_IDS.0 as u16;
+    }
+}
+
 /// Safe wrapper for [sqfs_super_t]
+#[derive(Clone)]
 pub struct SuperBlock {
     super_block: sqfs_super_t,
 }
@@ -29,7 +51,24 @@ impl SuperBlock {
         crate::sqfs_check(code, "Writing SuperBlock to file").map(|_| ())
     }
 
-    // TODO: wrap init
+    /// Safe wrapper for [sqfs_super_init]
+    ///
+    /// `block_size` has to be a power of two between 4 KiB and 1 MiB. The table locations,
+    /// counts and `bytes_used` are left for the writer to fill in with the setters below.
+    pub fn new(block_size: u32, mtime: u32, compressor: CompressorType) -> Result<Self> {
+        let init = |ptr| unsafe {
+            sqfs_super_init(
+                ptr,
+                usize::try_from(block_size).expect("blocksize should fit in a usize"),
+                mtime,
+                compressor.to_u32().expect("invalid compression type"),
+            )
+        };
+
+        let super_block = crate::sqfs_init(&init, "Initializing SuperBlock")?;
+
+        Ok(Self { super_block })
+    }
 
     pub fn magic(&self) -> u32 {
         self.super_block.magic
@@ -53,8 +92,9 @@ impl SuperBlock {
     pub fn block_log(&self) -> u16 {
         self.super_block.block_log
     }
-    pub fn flags(&self) -> u16 {
-        self.super_block.flags
+    /// Unknown bits are kept, so writing the super block back doesn't lose them.
+    pub fn flags(&self) -> SuperFlags {
+        SuperFlags::from_bits_retain(self.super_block.flags)
     }
     pub fn id_count(&self) -> u16 {
         self.super_block.id_count
@@ -90,6 +130,46 @@ impl SuperBlock {
         self.super_block.export_table_start
     }
 
+    pub fn set_inode_count(&mut self, inode_count: u32) {
+        self.super_block.inode_count = inode_count;
+    }
+    pub fn set_modification_time(&mut self, modification_time: u32) {
+        self.super_block.modification_time = modification_time;
+    }
+    pub fn set_fragment_entry_count(&mut self, fragment_entry_count: u32) {
+        self.super_block.fragment_entry_count = fragment_entry_count;
+    }
+    pub fn set_flags(&mut self, flags: SuperFlags) {
+        self.super_block.flags = flags.bits();
+    }
+    pub fn set_id_count(&mut self, id_count: u16) {
+        self.super_block.id_count = id_count;
+    }
+    pub fn set_root_inode_ref(&mut self, root_inode_ref: u64) {
+        self.super_block.root_inode_ref = root_inode_ref;
+    }
+    pub fn set_bytes_used(&mut self, bytes_used: u64) {
+        self.super_block.bytes_used = bytes_used;
+    }
+    pub fn set_id_table_start(&mut self, id_table_start: u64) {
+        self.super_block.id_table_start = id_table_start;
+    }
+    pub fn set_xattr_id_table_start(&mut self, xattr_id_table_start: u64) {
+        self.super_block.xattr_id_table_start = xattr_id_table_start;
+    }
+    pub fn set_inode_table_start(&mut self, inode_table_start: u64) {
+        self.super_block.inode_table_start = inode_table_start;
+    }
+    pub fn set_directory_table_start(&mut self, directory_table_start: u64) {
+        self.super_block.directory_table_start = directory_table_start;
+    }
+    pub fn set_fragment_table_start(&mut self, fragment_table_start: u64) {
+        self.super_block.fragment_table_start = fragment_table_start;
+    }
+    pub fn set_export_table_start(&mut self, export_table_start: u64) {
+        self.super_block.export_table_start = export_table_start;
+    }
+
     pub(crate) fn ptr(&self) -> &sqfs_super_t {
         &self.super_block
     }
@@ -0,0 +1,1057 @@
+use std::collections::BTreeMap;
+#[cfg(unix)]
+use std::collections::HashMap;
+use std::ffi::CString;
+#[cfg(unix)]
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::block_processor::{BlockProcessor, SQFS_BLK_FLAGS};
+use crate::block_writer::{BlockWriter, SQFS_BLOCK_WRITER_FLAGS};
+use crate::compressor::{Compressor, CompressorConfig, CompressorOptions, GzipOptions};
+use crate::directory_writer::{DirectoryWriter, SQFS_DIR_WRITER_CREATE_FLAGS};
+use crate::ffi::SQFS_INODE_MODE;
+use crate::file::{File, SQFS_FILE_OPEN_FLAGS};
+use crate::fragment::FragmentTable;
+use crate::id::IdTable;
+use crate::inode::{INodeType, OwnedINode};
+use crate::meta_writer::{MetaWriter, SQFS_META_WRITER_FLAGS};
+use crate::super_block::{SuperBlock, SuperFlags};
+use crate::xattr_writer::XattrWriter;
+use crate::{Result, SqfsError};
+
+/// Block size used unless [WriterOptions::compressor] says otherwise, the same as mksquashfs.
+const DEFAULT_BLOCK_SIZE: u32 = 131072;
+/// The root directory is always the first node of the tree.
+const ROOT: usize = 0;
+
+/// Options used by [ImageWriter] when creating an image.
+#[derive(Clone)]
+pub struct WriterOptions {
+    compressor: Option<CompressorConfig>,
+    modification_time: Option<u32>,
+    exportable: bool,
+    xattrs: bool,
+    threads: usize,
+    deduplicate: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            compressor: None,
+            modification_time: None,
+            exportable: false,
+            xattrs: true,
+            threads: 1,
+            deduplicate: true,
+        }
+    }
+}
+
+impl WriterOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Built with one of the [CompressorOptions], which also picks the block size of the image.
+    /// Defaults to gzip with 128 KiB blocks.
+    pub fn compressor(mut self, config: CompressorConfig) -> Self {
+        self.compressor = Some(config);
+        self
+    }
+
+    /// Stored in the super block. Defaults to the current time.
+    pub fn modification_time(mut self, modification_time: u32) -> Self {
+        self.modification_time = Some(modification_time);
+        self
+    }
+
+    /// Write an export table so the image can be exported through NFS.
+    pub fn exportable(mut self, exportable: bool) -> Self {
+        self.exportable = exportable;
+        self
+    }
+
+    /// Store extended attributes. Defaults to `true`.
+    ///
+    /// Attributes in a namespace squashfs can't store are skipped. Directories on disk only
+    /// have their attributes read on Linux.
+    pub fn xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Number of threads compressing data blocks. Defaults to `1`, which compresses everything
+    /// on the calling thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Store identical data blocks and file tails only once, like mksquashfs. Defaults to
+    /// `true`.
+    ///
+    /// Written blocks and fragments are indexed by a hash of their contents, and a file whose
+    /// blocks match ones already written refers to those instead. The number of duplicates
+    /// found is reported by [ImageWriter::stats].
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Creates an [ImageWriter] writing to `path`, replacing any file already there.
+    pub fn create<P: AsRef<Path>>(&self, path: P) -> Result<ImageWriter> {
+        let file = File::open(path, SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_OVERWRITE)?;
+
+        ImageWriter::new(file, self)
+    }
+
+    /// Writes an image of the directory at `source` to `destination`.
+    #[cfg(unix)]
+    pub fn write_directory<S: AsRef<Path>, D: AsRef<Path>>(
+        &self,
+        source: S,
+        destination: D,
+    ) -> Result<()> {
+        let mut writer = self.create(destination)?;
+        writer.add_directory(source)?;
+
+        writer.finish()
+    }
+}
+
+/// Statistics of an [ImageWriter], see [ImageWriter::stats].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriterStats {
+    bytes_read: u64,
+    bytes_written: u64,
+    data_blocks: u64,
+    fragment_blocks: u64,
+    sparse_blocks: u64,
+    duplicate_blocks: u64,
+    fragments: u64,
+    duplicate_fragments: u64,
+}
+
+impl WriterStats {
+    /// Size of all file contents added.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Size of the data and fragment blocks after compression, including duplicates.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Full data blocks of files, including duplicates but not sparse blocks.
+    pub fn data_blocks(&self) -> u64 {
+        self.data_blocks
+    }
+
+    /// Blocks that file tails were packed into.
+    pub fn fragment_blocks(&self) -> u64 {
+        self.fragment_blocks
+    }
+
+    /// Blocks of only zeros, which take up no space.
+    pub fn sparse_blocks(&self) -> u64 {
+        self.sparse_blocks
+    }
+
+    /// Blocks that were already in the image and weren't written again.
+    pub fn duplicate_blocks(&self) -> u64 {
+        self.duplicate_blocks
+    }
+
+    /// File tails, including duplicates.
+    pub fn fragments(&self) -> u64 {
+        self.fragments
+    }
+
+    /// File tails that were already in a fragment block and weren't stored again.
+    pub fn duplicate_fragments(&self) -> u64 {
+        self.duplicate_fragments
+    }
+}
+
+/// Writes a complete image to a [File].
+///
+/// File contents are compressed and written as soon as they are added. The inodes, directories
+/// and tables are written by [ImageWriter::finish], after which nothing can be added anymore.
+pub struct ImageWriter {
+    // Declared in the order they have to be dropped, since each refers to the ones after it.
+    block_processor: BlockProcessor,
+    block_writer: BlockWriter,
+    fragment_table: FragmentTable,
+    id_table: IdTable,
+    xattr_writer: Option<XattrWriter>,
+    tree: Tree,
+    block_flags: SQFS_BLK_FLAGS,
+    exportable: bool,
+    finished: bool,
+    super_block: SuperBlock,
+    compressor: Compressor,
+    file: File,
+}
+
+impl ImageWriter {
+    /// Starts an image in `file`, which should be empty.
+    pub fn new(file: File, options: &WriterOptions) -> Result<Self> {
+        let config = match &options.compressor {
+            Some(config) => config.clone(),
+            None => GzipOptions::new().config(DEFAULT_BLOCK_SIZE)?,
+        };
+        let modification_time = match options.modification_time {
+            Some(modification_time) => modification_time,
+            None => {
+                let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+                u32::try_from(now.as_secs()).unwrap_or(u32::MAX)
+            }
+        };
+
+        let mut super_block = SuperBlock::new(
+            config.block_size(),
+            modification_time,
+            config.compressor_type(),
+        )?;
+        let compressor = Compressor::new(&config)?;
+
+        // Reserve the space of the super block, it is written again once everything else is.
+        super_block.write(&file)?;
+        let mut flags = SuperFlags::empty();
+        if compressor.write_options(&file)? > 0 {
+            flags |= SuperFlags::COMPRESSOR_OPTIONS;
+        }
+        if options.exportable {
+            flags |= SuperFlags::EXPORTABLE;
+        }
+        if !options.xattrs {
+            flags |= SuperFlags::NO_XATTRS;
+        }
+        if options.deduplicate {
+            flags |= SuperFlags::DUPLICATES;
+        }
+        super_block.set_flags(flags);
+
+        let block_writer = BlockWriter::new(&file, crate::PAD_TO, SQFS_BLOCK_WRITER_FLAGS(0))?;
+        let fragment_table = FragmentTable::new()?;
+        let threads = options.threads.max(1);
+        let block_processor = BlockProcessor::new(
+            config.block_size(),
+            &compressor,
+            u32::try_from(threads).unwrap_or(u32::MAX),
+            threads * 10,
+            &block_writer,
+            &fragment_table,
+        )?;
+        let id_table = IdTable::new()?;
+        // Index 0 has to be id 0, the root and implicitly created directories are owned by it.
+        id_table.id_to_index(0)?;
+        let xattr_writer = if options.xattrs {
+            Some(XattrWriter::new()?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            block_processor,
+            block_writer,
+            fragment_table,
+            id_table,
+            xattr_writer,
+            tree: Tree::new(modification_time),
+            block_flags: if options.deduplicate {
+                SQFS_BLK_FLAGS(0)
+            } else {
+                SQFS_BLK_FLAGS::SQFS_BLK_DONT_DEDUPLICATE
+            },
+            exportable: options.exportable,
+            finished: false,
+            super_block,
+            compressor,
+            file,
+        })
+    }
+
+    /// Adds everything inside the directory at `source` to the root of the image.
+    ///
+    /// The root takes on the ownership, permissions and modification time of `source`. Hard
+    /// links are kept as long as all names are inside `source`.
+    #[cfg(unix)]
+    pub fn add_directory<P: AsRef<Path>>(&mut self, source: P) -> Result<()> {
+        self.check_finished()?;
+
+        let source = source.as_ref();
+        let metadata = fs::metadata(source)?;
+        if !metadata.is_dir() {
+            return Err(SqfsError::WrongType(
+                source.display().to_string(),
+                "file".to_string(),
+                "directory".to_string(),
+            ));
+        }
+
+        // Only the metadata of the source is taken, the root keeps whatever was added to it.
+        let attributes = self.attributes(&metadata)?;
+        let xattr_index = self.read_xattrs(source)?;
+        let root = &mut self.tree.nodes[ROOT];
+        root.mode = attributes.mode;
+        root.uid_index = attributes.uid_index;
+        root.gid_index = attributes.gid_index;
+        root.modification_time = attributes.modification_time;
+        root.xattr_index = xattr_index;
+
+        self.add_children(ROOT, source, &mut HashMap::new())
+    }
+
+    #[cfg(unix)]
+    fn add_children(
+        &mut self,
+        parent: usize,
+        directory: &Path,
+        hard_links: &mut HashMap<(u64, u64), usize>,
+    ) -> Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let mut entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+        // Sorted so the same directory always results in the same image.
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let name = entry.file_name().as_bytes().to_vec();
+            let metadata = fs::symlink_metadata(&path)?;
+            let file_type = metadata.file_type();
+            let id = (metadata.dev(), metadata.ino());
+
+            if !file_type.is_dir() && metadata.nlink() > 1 {
+                if let Some(&index) = hard_links.get(&id) {
+                    self.tree.link(parent, name, index);
+                    continue;
+                }
+            }
+
+            let kind = if file_type.is_dir() {
+                NodeKind::Directory(BTreeMap::new())
+            } else if file_type.is_file() {
+                NodeKind::File(self.write_file(&mut fs::File::open(&path)?)?)
+            } else if file_type.is_symlink() {
+                NodeKind::SymbolicLink(fs::read_link(&path)?.as_os_str().as_bytes().to_vec())
+            } else if file_type.is_block_device() {
+                NodeKind::BlockDevice(device_number(&metadata)?)
+            } else if file_type.is_char_device() {
+                NodeKind::CharacterDevice(device_number(&metadata)?)
+            } else if file_type.is_fifo() {
+                NodeKind::Fifo
+            } else if file_type.is_socket() {
+                NodeKind::Socket
+            } else {
+                return Err(SqfsError::WriteType(file_type));
+            };
+
+            let attributes = self.attributes(&metadata)?;
+            let node = Node {
+                kind,
+                mode: attributes.mode,
+                uid_index: attributes.uid_index,
+                gid_index: attributes.gid_index,
+                modification_time: attributes.modification_time,
+                xattr_index: self.read_xattrs(&path)?,
+                links: 0,
+            };
+            let index = self.tree.insert(parent, name, node);
+
+            if file_type.is_dir() {
+                self.add_children(index, &path, hard_links)?;
+            } else if metadata.nlink() > 1 {
+                hard_links.insert(id, index);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn attributes(&self, metadata: &std::fs::Metadata) -> Result<Attributes> {
+        use std::os::unix::fs::MetadataExt;
+
+        Ok(Attributes {
+            mode: u16::try_from(metadata.mode() & 0o7777).expect("permissions should fit in u16"),
+            uid_index: self.id_table.id_to_index(metadata.uid())?,
+            gid_index: self.id_table.id_to_index(metadata.gid())?,
+            modification_time: u32::try_from(metadata.mtime().max(0)).unwrap_or(u32::MAX),
+        })
+    }
+
+    /// Extended attributes are only read from disk on Linux.
+    #[cfg(all(unix, not(target_os = "linux")))]
+    fn read_xattrs(&self, _path: &Path) -> Result<u32> {
+        Ok(crate::NO_XATTRS)
+    }
+
+    /// Reads the extended attributes of `path` without following symbolic links and stores
+    /// them, returning the index for the inode.
+    #[cfg(target_os = "linux")]
+    fn read_xattrs(&self, path: &Path) -> Result<u32> {
+        use std::os::unix::ffi::OsStrExt;
+
+        if self.xattr_writer.is_none() {
+            return Ok(crate::NO_XATTRS);
+        }
+
+        let c_path = CString::new(path.as_os_str().as_bytes())?;
+        let error = || SqfsError::Xattr(path.to_path_buf(), io::Error::last_os_error());
+
+        let size = unsafe { libc::llistxattr(c_path.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return match io::Error::last_os_error().raw_os_error() {
+                Some(libc::ENOTSUP) => Ok(crate::NO_XATTRS),
+                _ => Err(error()),
+            };
+        }
+
+        let mut names = vec![0u8; usize::try_from(size).expect("positive isize should fit")];
+        let size = unsafe {
+            libc::llistxattr(
+                c_path.as_ptr(),
+                names.as_mut_ptr() as *mut libc::c_char,
+                names.len(),
+            )
+        };
+        if size < 0 {
+            return Err(error());
+        }
+        names.truncate(usize::try_from(size).expect("positive isize should fit"));
+
+        let mut xattrs = Vec::new();
+        for name in names
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+        {
+            let key = CString::new(name)?;
+            if !XattrWriter::supports(&key) {
+                continue;
+            }
+
+            let size =
+                unsafe { libc::lgetxattr(c_path.as_ptr(), key.as_ptr(), std::ptr::null_mut(), 0) };
+            if size < 0 {
+                return Err(error());
+            }
+            let mut value = vec![0u8; usize::try_from(size).expect("positive isize should fit")];
+            let size = unsafe {
+                libc::lgetxattr(
+                    c_path.as_ptr(),
+                    key.as_ptr(),
+                    value.as_mut_ptr() as *mut libc::c_void,
+                    value.len(),
+                )
+            };
+            if size < 0 {
+                return Err(error());
+            }
+            value.truncate(usize::try_from(size).expect("positive isize should fit"));
+
+            xattrs.push((key, value));
+        }
+
+        self.store_xattrs(&xattrs)
+    }
+
+    /// Stores a set of extended attributes, returning the index for the inode.
+    pub(crate) fn store_xattrs(&self, xattrs: &[(CString, Vec<u8>)]) -> Result<u32> {
+        let xattr_writer = match &self.xattr_writer {
+            Some(xattr_writer) if !xattrs.is_empty() => xattr_writer,
+            _ => return Ok(crate::NO_XATTRS),
+        };
+
+        xattr_writer.begin()?;
+        for (key, value) in xattrs {
+            xattr_writer.add(key, value)?;
+        }
+
+        xattr_writer.end()
+    }
+
+    /// Compresses everything `reader` returns as the contents of a file.
+    ///
+    /// Returns the position of the file's inode in the list returned by
+    /// [BlockProcessor::finish].
+    pub(crate) fn write_file<R: Read>(&mut self, reader: &mut R) -> Result<usize> {
+        let index = self.block_processor.begin_file(self.block_flags)?;
+        let mut buffer = [0u8; crate::BLOCK_BUF_SIZE];
+
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => self.block_processor.append(&buffer[..read])?,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        self.block_processor.end_file()?;
+
+        Ok(index)
+    }
+
+    /// Number of data blocks written to the image so far.
+    pub fn block_count(&self) -> u64 {
+        self.block_writer.block_count()
+    }
+
+    /// Statistics about the file contents written, final once [ImageWriter::finish] returned.
+    pub fn stats(&self) -> WriterStats {
+        let stats = self.block_processor.stats();
+        // Data and fragment blocks that were not written again are the duplicates.
+        let blocks = stats.data_block_count + stats.frag_block_count;
+
+        WriterStats {
+            bytes_read: stats.input_bytes_read,
+            bytes_written: stats.output_bytes_generated,
+            data_blocks: stats.data_block_count,
+            fragment_blocks: stats.frag_block_count,
+            sparse_blocks: stats.sparse_block_count,
+            duplicate_blocks: blocks.saturating_sub(self.block_writer.block_count()),
+            fragments: stats.total_frag_count,
+            duplicate_fragments: stats
+                .total_frag_count
+                .saturating_sub(stats.actual_frag_count),
+        }
+    }
+
+    pub(crate) fn check_finished(&self) -> Result<()> {
+        if self.finished {
+            return Err(SqfsError::Finished);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub(crate) fn modification_time(&self) -> u32 {
+        self.super_block.modification_time()
+    }
+
+    /// Index of `id` in the id table, adding it if needed.
+    pub(crate) fn id_index(&self, id: u32) -> Result<u16> {
+        self.id_table.id_to_index(id)
+    }
+
+    /// Adds `node` at `path` inside the image, creating missing parent directories.
+    ///
+    /// A directory that is already there, including the root, only takes on the metadata of
+    /// `node` so directories can be described after their contents.
+    pub(crate) fn insert_path(&mut self, path: &Path, node: Node) -> Result<()> {
+        match self.parent(path)? {
+            None => self.tree.update_directory(ROOT, node, path),
+            Some((parent, name)) => match self.tree.child(parent, &name) {
+                Some(index) => self.tree.update_directory(index, node, path),
+                None => {
+                    self.tree.insert(parent, name, node);
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    /// Fails like [ImageWriter::insert_path] would for a node that isn't a directory, but
+    /// without changing anything. Lets file contents be checked for a place before they are
+    /// written.
+    pub(crate) fn check_vacant(&self, path: &Path) -> Result<()> {
+        let names = path_names(path)?;
+        let mut directory = ROOT;
+        let mut walked = PathBuf::from("/");
+        for (position, component) in names.iter().enumerate() {
+            walked.push(String::from_utf8_lossy(component).as_ref());
+            let index = match self.tree.child(directory, component) {
+                Some(index) => index,
+                // Missing parents are created, so everything below is free too.
+                None => return Ok(()),
+            };
+
+            if position + 1 == names.len() {
+                break;
+            }
+            if !matches!(self.tree.nodes[index].kind, NodeKind::Directory(_)) {
+                return Err(SqfsError::WrongType(
+                    walked.display().to_string(),
+                    format!("{:?}", self.tree.nodes[index].tipe()),
+                    "Directory".to_string(),
+                ));
+            }
+            directory = index;
+        }
+
+        Err(SqfsError::Exists(path.to_path_buf()))
+    }
+
+    /// Adds another name at `path` for the node at `target`, which can't be a directory.
+    pub(crate) fn link_path(&mut self, path: &Path, target: &Path) -> Result<()> {
+        let index = self
+            .lookup(target)?
+            .ok_or_else(|| SqfsError::NotFound(target.to_path_buf()))?;
+        if let NodeKind::Directory(_) = self.tree.nodes[index].kind {
+            return Err(SqfsError::WrongType(
+                target.display().to_string(),
+                "Directory".to_string(),
+                "File".to_string(),
+            ));
+        }
+
+        let (parent, name) = self
+            .parent(path)?
+            .ok_or_else(|| SqfsError::Exists(path.to_path_buf()))?;
+        if self.tree.child(parent, &name).is_some() {
+            return Err(SqfsError::Exists(path.to_path_buf()));
+        }
+        self.tree.link(parent, name, index);
+
+        Ok(())
+    }
+
+    fn lookup(&self, path: &Path) -> Result<Option<usize>> {
+        let mut index = ROOT;
+        for name in path_names(path)? {
+            index = match self.tree.child(index, &name) {
+                Some(child) => child,
+                None => return Ok(None),
+            };
+        }
+
+        Ok(Some(index))
+    }
+
+    /// Finds the directory that `path` goes into and the name it gets there, creating the
+    /// directory and its parents if they are missing. Returns `None` for the root itself.
+    fn parent(&mut self, path: &Path) -> Result<Option<(usize, Vec<u8>)>> {
+        let mut names = path_names(path)?;
+        let name = match names.pop() {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+
+        let mut directory = ROOT;
+        let mut walked = PathBuf::from("/");
+        for component in names {
+            walked.push(String::from_utf8_lossy(&component).as_ref());
+            directory = match self.tree.child(directory, &component) {
+                Some(index) => match &self.tree.nodes[index].kind {
+                    NodeKind::Directory(_) => index,
+                    _ => {
+                        return Err(SqfsError::WrongType(
+                            walked.display().to_string(),
+                            format!("{:?}", self.tree.nodes[index].tipe()),
+                            "Directory".to_string(),
+                        ))
+                    }
+                },
+                None => {
+                    let node = Node::directory(self.modification_time());
+                    self.tree.insert(directory, component, node)
+                }
+            };
+        }
+
+        Ok(Some((directory, name)))
+    }
+
+    /// Writes the inodes, directories and tables and then the final super block.
+    ///
+    /// The image is padded to a multiple of 4 KiB. Images without any entries besides the root
+    /// are refused with [SqfsError::Empty].
+    pub fn finish(&mut self) -> Result<()> {
+        self.check_finished()?;
+
+        if self.tree.is_empty() {
+            return Err(SqfsError::Empty);
+        }
+        self.finished = true;
+
+        let file_inodes = self.block_processor.finish()?;
+        self.write_inodes(file_inodes)?;
+
+        self.fragment_table
+            .write(&self.file, &mut self.super_block, &self.compressor)?;
+        self.id_table
+            .write(&self.file, &mut self.super_block, &self.compressor)?;
+        if let Some(xattr_writer) = &self.xattr_writer {
+            xattr_writer.flush(&self.file, &mut self.super_block, &self.compressor)?;
+        }
+
+        let bytes_used = self.file.get_size();
+        self.super_block.set_bytes_used(bytes_used);
+        self.super_block.write(&self.file)?;
+
+        let pad_to = u64::try_from(crate::PAD_TO).expect("usize should fit in u64");
+        let padded = bytes_used.div_ceil(pad_to) * pad_to;
+        if padded != bytes_used {
+            self.file.truncate(padded)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the inode table and directory table, and the export table if requested.
+    fn write_inodes(&mut self, file_inodes: Vec<OwnedINode>) -> Result<()> {
+        let numbers = self.tree.number_inodes();
+        let inode_count = numbers[ROOT];
+
+        self.super_block.set_inode_table_start(self.file.get_size());
+
+        let inode_writer =
+            MetaWriter::new(&self.file, &self.compressor, SQFS_META_WRITER_FLAGS(0))?;
+        let directory_meta_writer = MetaWriter::new(
+            &self.file,
+            &self.compressor,
+            SQFS_META_WRITER_FLAGS::SQFS_META_WRITER_KEEP_IN_MEMORY,
+        )?;
+        let directory_writer = DirectoryWriter::new(
+            &directory_meta_writer,
+            if self.exportable {
+                SQFS_DIR_WRITER_CREATE_FLAGS::SQFS_DIR_WRITER_CREATE_EXPORT_TABLE
+            } else {
+                SQFS_DIR_WRITER_CREATE_FLAGS(0)
+            },
+        )?;
+
+        let mut serializer = Serializer {
+            tree: &self.tree,
+            numbers: &numbers,
+            references: vec![None; numbers.len()],
+            file_inodes: file_inodes.into_iter().map(Some).collect(),
+            inode_writer: &inode_writer,
+            directory_writer: &directory_writer,
+        };
+        // The kernel expects the parent of the root to be one past the last inode.
+        let root_ref = serializer.write(ROOT, inode_count + 1)?;
+        inode_writer.flush()?;
+
+        self.super_block
+            .set_directory_table_start(self.file.get_size());
+        directory_meta_writer.flush()?;
+        directory_meta_writer.write_to_file()?;
+
+        self.super_block.set_inode_count(inode_count);
+        self.super_block.set_root_inode_ref(root_ref);
+
+        if self.exportable {
+            directory_writer.write_export_table(
+                &self.file,
+                &self.compressor,
+                inode_count,
+                root_ref,
+                &mut self.super_block,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Squashfs stores device numbers in the same 32 bit encoding Linux uses.
+#[cfg(unix)]
+fn device_number(metadata: &std::fs::Metadata) -> Result<u32> {
+    use std::os::unix::fs::MetadataExt;
+
+    u32::try_from(metadata.rdev())
+        .map_err(|_| SqfsError::Unsupported(format!("device number {}", metadata.rdev())))
+}
+
+/// Splits a path inside the image into the names of its components.
+fn path_names(path: &Path) -> Result<Vec<Vec<u8>>> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name_bytes(name)),
+            Component::RootDir | Component::CurDir => None,
+            Component::ParentDir | Component::Prefix(_) => Some(Err(SqfsError::Unsupported(
+                format!("path {} outside of the image root", path.display()),
+            ))),
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+pub(crate) fn name_bytes(name: &std::ffi::OsStr) -> Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+
+    Ok(name.as_bytes().to_vec())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn name_bytes(name: &std::ffi::OsStr) -> Result<Vec<u8>> {
+    name.to_str()
+        .map(|name| name.as_bytes().to_vec())
+        .ok_or_else(|| SqfsError::OsUtf8(name.to_os_string()))
+}
+
+/// Everything added to an [ImageWriter], kept until the inodes are written.
+struct Tree {
+    nodes: Vec<Node>,
+}
+
+impl Tree {
+    fn new(modification_time: u32) -> Self {
+        Self {
+            nodes: vec![Node {
+                links: 1,
+                ..Node::directory(modification_time)
+            }],
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.nodes.len() == 1
+    }
+
+    /// Adds `node` as `name` to the directory at `parent` and returns its index.
+    fn insert(&mut self, parent: usize, name: Vec<u8>, node: Node) -> usize {
+        self.nodes.push(node);
+        let index = self.nodes.len() - 1;
+        self.link(parent, name, index);
+
+        index
+    }
+
+    fn child(&self, parent: usize, name: &[u8]) -> Option<usize> {
+        match &self.nodes[parent].kind {
+            NodeKind::Directory(children) => children.get(name).copied(),
+            _ => None,
+        }
+    }
+
+    /// Gives the directory at `index` the metadata of `node`, which has to be a directory too.
+    fn update_directory(&mut self, index: usize, node: Node, path: &Path) -> Result<()> {
+        let existing = &mut self.nodes[index];
+        if !matches!(
+            (&existing.kind, &node.kind),
+            (NodeKind::Directory(_), NodeKind::Directory(_))
+        ) {
+            return Err(SqfsError::Exists(path.to_path_buf()));
+        }
+
+        existing.mode = node.mode;
+        existing.uid_index = node.uid_index;
+        existing.gid_index = node.gid_index;
+        existing.modification_time = node.modification_time;
+        existing.xattr_index = node.xattr_index;
+
+        Ok(())
+    }
+
+    /// Adds another name for the node at `index` to the directory at `parent`.
+    fn link(&mut self, parent: usize, name: Vec<u8>, index: usize) {
+        self.nodes[index].links += 1;
+
+        match &mut self.nodes[parent].kind {
+            NodeKind::Directory(children) => {
+                children.insert(name, index);
+            }
+            _ => unreachable!("parent should be a directory"),
+        }
+    }
+
+    /// Numbers every inode so that children come before their directory, ending with the root.
+    fn number_inodes(&self) -> Vec<u32> {
+        let mut numbers = vec![0; self.nodes.len()];
+        let mut next = 1;
+        self.number_inode(ROOT, &mut numbers, &mut next);
+
+        numbers
+    }
+
+    fn number_inode(&self, index: usize, numbers: &mut [u32], next: &mut u32) {
+        if numbers[index] != 0 {
+            return;
+        }
+
+        if let NodeKind::Directory(children) = &self.nodes[index].kind {
+            for &child in children.values() {
+                self.number_inode(child, numbers, next);
+            }
+        }
+
+        numbers[index] = *next;
+        *next += 1;
+    }
+}
+
+/// Permissions, owner and modification time of a file on disk, as stored in a [Node].
+#[cfg(unix)]
+struct Attributes {
+    mode: u16,
+    uid_index: u16,
+    gid_index: u16,
+    modification_time: u32,
+}
+
+pub(crate) struct Node {
+    pub(crate) kind: NodeKind,
+    /// Permission bits without the file type.
+    pub(crate) mode: u16,
+    pub(crate) uid_index: u16,
+    pub(crate) gid_index: u16,
+    pub(crate) modification_time: u32,
+    pub(crate) xattr_index: u32,
+    /// Number of directory entries referring to the node.
+    pub(crate) links: u32,
+}
+
+impl Node {
+    /// An empty directory owned by id 0, as created for missing parents.
+    fn directory(modification_time: u32) -> Self {
+        Self {
+            kind: NodeKind::Directory(BTreeMap::new()),
+            mode: 0o755,
+            uid_index: 0,
+            gid_index: 0,
+            modification_time,
+            xattr_index: crate::NO_XATTRS,
+            links: 0,
+        }
+    }
+
+    fn tipe(&self) -> INodeType {
+        match self.kind {
+            NodeKind::Directory(_) => INodeType::Directory,
+            NodeKind::File(_) => INodeType::File,
+            NodeKind::SymbolicLink(_) => INodeType::SymbolicLink,
+            NodeKind::BlockDevice(_) => INodeType::BlockDevice,
+            NodeKind::CharacterDevice(_) => INodeType::CharacterDev,
+            NodeKind::Fifo => INodeType::Fifo,
+            NodeKind::Socket => INodeType::Socket,
+        }
+    }
+
+    /// The permission bits along with the file type.
+    fn full_mode(&self) -> u16 {
+        let file_type = match self.kind {
+            NodeKind::Directory(_) => SQFS_INODE_MODE::SQFS_INODE_MODE_DIR,
+            NodeKind::File(_) => SQFS_INODE_MODE::SQFS_INODE_MODE_REG,
+            NodeKind::SymbolicLink(_) => SQFS_INODE_MODE::SQFS_INODE_MODE_LNK,
+            NodeKind::BlockDevice(_) => SQFS_INODE_MODE::SQFS_INODE_MODE_BLK,
+            NodeKind::CharacterDevice(_) => SQFS_INODE_MODE::SQFS_INODE_MODE_CHR,
+            NodeKind::Fifo => SQFS_INODE_MODE::SQFS_INODE_MODE_FIFO,
+            NodeKind::Socket => SQFS_INODE_MODE::SQFS_INODE_MODE_SOCK,
+        };
+
+        u16::try_from(file_type.0).expect("file type should fit in u16") | self.mode
+    }
+}
+
+pub(crate) enum NodeKind {
+    /// Children by name, sorted the way squashfs expects.
+    Directory(BTreeMap<Vec<u8>, usize>),
+    /// Position of the inode in the list returned by [BlockProcessor::finish].
+    File(usize),
+    SymbolicLink(Vec<u8>),
+    BlockDevice(u32),
+    CharacterDevice(u32),
+    Fifo,
+    Socket,
+}
+
+/// Writes the inodes of a [Tree] depth first, so every directory comes after its children.
+struct Serializer<'a> {
+    tree: &'a Tree,
+    numbers: &'a [u32],
+    references: Vec<Option<u64>>,
+    file_inodes: Vec<Option<OwnedINode>>,
+    inode_writer: &'a MetaWriter,
+    directory_writer: &'a DirectoryWriter,
+}
+
+impl<'a> Serializer<'a> {
+    /// Writes the node at `index` after everything below it and returns its inode reference.
+    fn write(&mut self, index: usize, parent_number: u32) -> Result<u64> {
+        // Nodes with several names are only written once.
+        if let Some(reference) = self.references[index] {
+            return Ok(reference);
+        }
+
+        let node = &self.tree.nodes[index];
+        let number = self.numbers[index];
+
+        let mut inode = match &node.kind {
+            NodeKind::Directory(children) => {
+                self.write_directory(children, node, number, parent_number)?
+            }
+            NodeKind::File(position) => self.file_inodes[*position].take().ok_or_else(|| {
+                SqfsError::Internal(format!("file inode {} was already written", number))
+            })?,
+            NodeKind::SymbolicLink(target) => {
+                let mut inode = OwnedINode::alloc(node.tipe(), target.len())?;
+                inode.payload_mut().copy_from_slice(target);
+                inode.as_mut().data.slink.target_size =
+                    u32::try_from(target.len()).expect("payload size was checked");
+                inode
+            }
+            NodeKind::BlockDevice(device_number) | NodeKind::CharacterDevice(device_number) => {
+                let mut inode = OwnedINode::alloc(node.tipe(), 0)?;
+                inode.as_mut().data.dev.devno = *device_number;
+                inode
+            }
+            NodeKind::Fifo | NodeKind::Socket => OwnedINode::alloc(node.tipe(), 0)?,
+        };
+
+        if !matches!(node.kind, NodeKind::Directory(_)) {
+            inode.set_number_of_hard_links(node.links)?;
+            inode.set_xattr_index(node.xattr_index)?;
+        }
+
+        let base = &mut inode.as_mut().base;
+        base.mode = node.full_mode();
+        base.uid_idx = node.uid_index;
+        base.gid_idx = node.gid_index;
+        base.mod_time = node.modification_time;
+        base.inode_number = number;
+
+        let reference = self.inode_writer.reference();
+        self.inode_writer.write_inode(&inode.as_inode())?;
+        self.references[index] = Some(reference);
+
+        Ok(reference)
+    }
+
+    fn write_directory(
+        &mut self,
+        children: &BTreeMap<Vec<u8>, usize>,
+        node: &Node,
+        number: u32,
+        parent_number: u32,
+    ) -> Result<OwnedINode> {
+        for &child in children.values() {
+            self.write(child, number)?;
+        }
+
+        self.directory_writer.begin()?;
+        for (name, &child) in children {
+            let reference =
+                self.references[child].ok_or(SqfsError::WriteOrder(self.numbers[child]))?;
+
+            self.directory_writer.add_entry(
+                &CString::new(name.as_slice())?,
+                self.numbers[child],
+                reference,
+                self.tree.nodes[child].full_mode(),
+            )?;
+        }
+        self.directory_writer.end()?;
+
+        let subdirectories = children
+            .values()
+            .filter(|&&child| matches!(self.tree.nodes[child].kind, NodeKind::Directory(_)))
+            .count();
+
+        self.directory_writer
+            .create_inode(2 + subdirectories, node.xattr_index, parent_number)
+    }
+}
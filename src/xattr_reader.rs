@@ -0,0 +1,102 @@
+use std::ffi::{c_char, CStr, OsString};
+use std::ptr::{self, NonNull};
+
+use crate::compressor::Compressor;
+use crate::ffi::{
+    sqfs_xattr_entry_t, sqfs_xattr_id_t, sqfs_xattr_reader_create, sqfs_xattr_reader_get_desc,
+    sqfs_xattr_reader_load, sqfs_xattr_reader_read_key, sqfs_xattr_reader_read_value,
+    sqfs_xattr_reader_seek_kv, sqfs_xattr_reader_t, sqfs_xattr_value_t,
+};
+use crate::file::File;
+use crate::inode::INode;
+use crate::super_block::SuperBlock;
+use crate::{ManagedPointer, Result, SqfsError};
+
+/// Safe wrapper for [sqfs_xattr_reader_t]
+pub struct XattrReader {
+    ptr: ManagedPointer<sqfs_xattr_reader_t>,
+}
+
+impl XattrReader {
+    /// Safe wrapper for [sqfs_xattr_reader_create]
+    pub fn new() -> Result<Self> {
+        let init = || unsafe { sqfs_xattr_reader_create(0) };
+
+        ManagedPointer::check_null(&init, "Creating XattrReader", crate::sqfs_destroy)
+            .map(|ptr| Self { ptr })
+    }
+
+    /// Safe wrapper for [sqfs_xattr_reader_load]
+    pub fn read(file: &File, super_block: &SuperBlock, compressor: &Compressor) -> Result<Self> {
+        let xattr_reader = Self::new()?;
+
+        let code = unsafe {
+            sqfs_xattr_reader_load(
+                xattr_reader.ptr.as_ptr(),
+                super_block.ptr(),
+                file.ptr().as_ptr(),
+                compressor.ptr().as_ptr(),
+            )
+        };
+
+        crate::sqfs_check(code, "Reading XattrReader")?;
+
+        Ok(xattr_reader)
+    }
+
+    /// Reads every key value pair stored at `index` of the xattr id table.
+    ///
+    /// Keys include their namespace prefix such as `user.` and out of line values are
+    /// followed transparently.
+    pub fn get(&self, index: u32) -> Result<Vec<(OsString, Vec<u8>)>> {
+        if index == crate::NO_XATTRS {
+            return Ok(Vec::new());
+        }
+
+        let init = |ptr| unsafe { sqfs_xattr_reader_get_desc(self.ptr.as_ptr(), index, ptr) };
+        let desc: sqfs_xattr_id_t =
+            crate::sqfs_init(&init, &format!("Looking up xattrs with index({})", index))?;
+
+        let code = unsafe { sqfs_xattr_reader_seek_kv(self.ptr.as_ptr(), &desc) };
+        crate::sqfs_check(code, "Seeking to xattr key value pairs")?;
+
+        (0..desc.count).map(|_| self.read_pair()).collect()
+    }
+
+    /// Reads the extended attributes of `inode`.
+    pub fn get_for_inode(&self, inode: &INode) -> Result<Vec<(OsString, Vec<u8>)>> {
+        self.get(inode.extended_attribute_index())
+    }
+
+    fn read_pair(&self) -> Result<(OsString, Vec<u8>)> {
+        let mut key_ptr: *mut sqfs_xattr_entry_t = ptr::null_mut();
+        let code = unsafe { sqfs_xattr_reader_read_key(self.ptr.as_ptr(), &mut key_ptr) };
+        crate::sqfs_check(code, "Reading xattr key")?;
+        let key = take(key_ptr, "Reading xattr key")?;
+
+        let mut value_ptr: *mut sqfs_xattr_value_t = ptr::null_mut();
+        let code = unsafe {
+            sqfs_xattr_reader_read_value(self.ptr.as_ptr(), key.as_ptr(), &mut value_ptr)
+        };
+        crate::sqfs_check(code, "Reading xattr value")?;
+        let value = take(value_ptr, "Reading xattr value")?;
+
+        // The library prepends the namespace prefix and terminates the key with a null byte.
+        let name = unsafe { CStr::from_ptr((*key.as_ptr()).key.as_ptr() as *const c_char) };
+        let name = crate::os_string_from_bytes(name.to_bytes())?;
+
+        let value = unsafe {
+            let value = &*value.as_ptr();
+            let len = usize::try_from(value.size).expect("u32 should fit in usize");
+            value.value.as_slice(len).to_vec()
+        };
+
+        Ok((name, value))
+    }
+}
+
+fn take<T>(ptr: *mut T, err: &str) -> Result<ManagedPointer<T>> {
+    NonNull::new(ptr)
+        .map(|ptr| ManagedPointer::new(ptr, crate::sqfs_free))
+        .ok_or(SqfsError::LibraryReturnError(err.to_string()))
+}
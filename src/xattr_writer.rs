@@ -0,0 +1,85 @@
+use std::ffi::{c_void, CStr};
+
+use crate::compressor::Compressor;
+use crate::ffi::{
+    sqfs_has_xattr, sqfs_xattr_writer_add, sqfs_xattr_writer_begin, sqfs_xattr_writer_create,
+    sqfs_xattr_writer_end, sqfs_xattr_writer_flush, sqfs_xattr_writer_t,
+};
+use crate::file::File;
+use crate::super_block::SuperBlock;
+use crate::{ManagedPointer, Result};
+
+/// Safe wrapper for [sqfs_xattr_writer_t]
+pub struct XattrWriter {
+    ptr: ManagedPointer<sqfs_xattr_writer_t>,
+}
+
+impl XattrWriter {
+    /// Safe wrapper for [sqfs_xattr_writer_create]
+    pub fn new() -> Result<Self> {
+        let init = || unsafe { sqfs_xattr_writer_create(0) };
+
+        ManagedPointer::check_null(&init, "Creating XattrWriter", crate::sqfs_destroy)
+            .map(|ptr| Self { ptr })
+    }
+
+    /// Safe wrapper for [sqfs_xattr_writer_begin]
+    pub fn begin(&self) -> Result<()> {
+        let code = unsafe { sqfs_xattr_writer_begin(self.ptr.as_ptr(), 0) };
+
+        crate::sqfs_check(code, "Beginning xattr block").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_xattr_writer_add]
+    ///
+    /// `key` has to include a namespace prefix squashfs supports, see [XattrWriter::supports].
+    pub fn add(&self, key: &CStr, value: &[u8]) -> Result<()> {
+        let code = unsafe {
+            sqfs_xattr_writer_add(
+                self.ptr.as_ptr(),
+                key.as_ptr(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+            )
+        };
+
+        crate::sqfs_check(code, &format!("Adding xattr {:?}", key)).map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_xattr_writer_end]
+    ///
+    /// Returns the index to store in the inode. Identical blocks share the same index.
+    pub fn end(&self) -> Result<u32> {
+        let init = |ptr| unsafe { sqfs_xattr_writer_end(self.ptr.as_ptr(), ptr) };
+
+        crate::sqfs_init(&init, "Ending xattr block")
+    }
+
+    /// Safe wrapper for [sqfs_xattr_writer_flush]
+    ///
+    /// Without any xattrs nothing is written and the super block is marked accordingly.
+    pub fn flush(
+        &self,
+        file: &File,
+        super_block: &mut SuperBlock,
+        compressor: &Compressor,
+    ) -> Result<()> {
+        let code = unsafe {
+            sqfs_xattr_writer_flush(
+                self.ptr.as_ptr(),
+                file.ptr().as_ptr(),
+                super_block.ptr_mut(),
+                compressor.ptr().as_ptr(),
+            )
+        };
+
+        crate::sqfs_check(code, "Writing xattrs to file").map(|_| ())
+    }
+
+    /// Safe wrapper for [sqfs_has_xattr]
+    ///
+    /// Whether the namespace prefix of `key` can be stored in an image.
+    pub fn supports(key: &CStr) -> bool {
+        unsafe { sqfs_has_xattr(key.as_ptr()) }
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use squashed::directory_reader::SQFS_TREE_FILTER_FLAGS;
+use squashed::inode::INode;
+use squashed::{Archive, ArchiveOptions};
+
+#[test]
+fn open_archive() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+
+    let id_table = archive.id_table().expect("id table");
+    let _ = archive
+        .directory_reader()
+        .expect("directory reader")
+        .get_full_hierarchy::<PathBuf>(id_table, None, SQFS_TREE_FILTER_FLAGS(0))
+        .expect("directory tree");
+}
+
+#[test]
+fn open_archive_eagerly() {
+    assert!(
+        ArchiveOptions::new()
+            .load_id_table(true)
+            .load_fragment_table(true)
+            .load_directory_reader(true)
+            .load_xattr_reader(true)
+            .open("../deltagen/imgs/new.img")
+            .is_ok(),
+        "archive should open with all tables loaded"
+    );
+}
+
+#[test]
+fn lookup_paths() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+
+    let root = archive.lookup("/").expect("root");
+    assert!(matches!(
+        root.as_inode(),
+        INode::Directory(_) | INode::ExtendedDirectory(_)
+    ));
+
+    let parent_of_root = archive.lookup("/../..").expect("parent of root");
+    assert_eq!(
+        root.as_inode().inode_number(),
+        parent_of_root.as_inode().inode_number()
+    );
+
+    assert!(
+        archive.lookup("/this/path/does/not/exist").is_err(),
+        "missing path should not resolve"
+    );
+}
+
+#[test]
+fn read_root_directory() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+
+    for entry in archive.read_dir("/").expect("read dir") {
+        let entry = entry.expect("directory entry");
+        let inode = entry.inode().expect("inode of entry");
+
+        assert_eq!(
+            archive
+                .lookup_no_follow(std::path::Path::new("/").join(entry.name()))
+                .expect("lookup of entry")
+                .as_inode()
+                .inode_number(),
+            inode.as_inode().inode_number()
+        );
+    }
+}
+
+#[test]
+fn read_root_xattrs() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let root = archive.lookup("/").expect("root");
+
+    let xattrs = archive
+        .xattr_reader()
+        .expect("xattr reader")
+        .get_for_inode(&root.as_inode())
+        .expect("xattrs");
+
+    for (key, _) in xattrs {
+        let key = key.to_string_lossy();
+        assert!(
+            ["user.", "trusted.", "security."]
+                .iter()
+                .any(|prefix| key.starts_with(prefix)),
+            "xattr key {} should carry a namespace prefix",
+            key
+        );
+    }
+}
+
+#[test]
+fn resolve_owner_of_root() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let id_table = archive.id_table().expect("id table");
+    let tree = archive
+        .directory_reader()
+        .expect("directory reader")
+        .get_full_hierarchy::<PathBuf>(id_table, None, SQFS_TREE_FILTER_FLAGS(0))
+        .expect("directory tree");
+
+    let root = archive.lookup("/").expect("root");
+    let root = root.as_inode();
+
+    assert_eq!(root.uid(id_table).expect("uid"), tree.root().uid());
+    assert_eq!(root.gid(id_table).expect("gid"), tree.root().gid());
+    assert_eq!(
+        id_table.id_to_index(tree.root().uid()).expect("index"),
+        root.uid_index()
+    );
+}
+
+#[test]
+fn open_archive_at_offset() {
+    let image = std::fs::read("../deltagen/imgs/new.img").expect("image");
+    let mut embedded = tempfile::NamedTempFile::new().expect("tempfile");
+    embedded.write_all(&[0xaa; 4096]).expect("padding");
+    embedded.write_all(&image).expect("image");
+
+    let archive = ArchiveOptions::new()
+        .offset(4096)
+        .open(embedded.path())
+        .expect("archive");
+
+    assert_eq!(
+        archive.file().get_size(),
+        u64::try_from(image.len()).expect("size")
+    );
+    assert!(archive.read_dir("/").is_ok(), "root should resolve");
+}
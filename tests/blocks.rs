@@ -0,0 +1,96 @@
+use squashed::builder::{ImageBuilder, Metadata};
+use squashed::inode::INode;
+use squashed::writer::WriterOptions;
+use squashed::{Archive, BlockAttributes};
+
+use common::open_image;
+
+mod common;
+
+#[test]
+fn read_fragment_blocks() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let block_size = archive.super_block().block_size() as usize;
+
+    for fragment in archive
+        .fragment_table()
+        .expect("fragment table")
+        .fragments()
+    {
+        let raw = fragment.read_raw(archive.file()).expect("raw fragment");
+        assert_eq!(raw.len(), fragment.size() as usize);
+
+        let data = fragment
+            .read(archive.file(), archive.compressor())
+            .expect("fragment");
+        assert!(data.len() <= block_size);
+        if !fragment.is_compressed() {
+            assert_eq!(data, raw);
+        }
+    }
+}
+
+#[test]
+fn read_data_blocks() {
+    let block_size = 131072;
+    let pattern = b"squashfs ".repeat(block_size / 9 + 1)[..block_size].to_vec();
+    let mut state = 0x9e37_79b9_u32;
+    let noise = (0..block_size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state.to_le_bytes()[0]
+        })
+        .collect::<Vec<_>>();
+    let contents = [&pattern[..], &vec![0u8; block_size], &noise[..]].concat();
+
+    let mut builder = ImageBuilder::in_memory(&WriterOptions::new()).expect("builder");
+    builder
+        .add_file("file", &Metadata::new(), &contents[..])
+        .expect("file");
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+
+    let archive = open_image(image);
+    assert_eq!(archive.super_block().block_size() as usize, block_size);
+    let inode = archive.lookup("/file").expect("lookup");
+    let blocks = match inode.as_inode() {
+        INode::File(file) => file.blocks().collect::<Vec<_>>(),
+        INode::ExtendedFile(file) => file.blocks().collect::<Vec<_>>(),
+        _ => panic!("/file should be a file"),
+    };
+    assert_eq!(blocks.len(), 3);
+
+    let (compressed, sparse, stored) = (&blocks[0], &blocks[1], &blocks[2]);
+    assert!(compressed.is_compressed());
+    assert_eq!(
+        compressed.read_raw(archive.file()).expect("raw").len(),
+        compressed.size() as usize
+    );
+    assert!((compressed.size() as usize) < block_size);
+    assert_eq!(
+        compressed
+            .read(archive.file(), archive.compressor())
+            .expect("compressed block"),
+        pattern
+    );
+
+    assert!(sparse.is_sparse());
+    assert!(sparse.read_raw(archive.file()).expect("raw").is_empty());
+    assert_eq!(
+        sparse
+            .read(archive.file(), archive.compressor())
+            .expect("sparse block"),
+        vec![0u8; block_size]
+    );
+
+    assert!(!stored.is_compressed());
+    assert_eq!(stored.read_raw(archive.file()).expect("raw"), noise);
+    assert_eq!(
+        stored
+            .read(archive.file(), archive.compressor())
+            .expect("stored block"),
+        noise
+    );
+}
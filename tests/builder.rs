@@ -0,0 +1,128 @@
+use std::fs;
+
+use squashed::builder::{device_number, DeviceType, ImageBuilder, Metadata};
+use squashed::file::File;
+use squashed::inode::INode;
+use squashed::writer::WriterOptions;
+use squashed::{Archive, SqfsError};
+
+use common::{open_image, read_file};
+
+mod common;
+
+#[test]
+fn build_in_memory() {
+    let options = WriterOptions::new().xattrs(false).modification_time(1000);
+    let mut builder = ImageBuilder::in_memory(&options).expect("builder");
+    let owned = Metadata::new().uid(1000).gid(100).mode(0o600);
+
+    builder
+        .add_file("etc/config", &owned, &b"key = value\n"[..])
+        .expect("file");
+    builder
+        .add_dir("etc", &Metadata::new().mode(0o750))
+        .expect("describe implicit directory");
+    builder
+        .add_symlink("config", &Metadata::new(), "etc/config")
+        .expect("symlink");
+    builder
+        .add_device(
+            "dev/null",
+            &Metadata::new(),
+            DeviceType::Character,
+            device_number(1, 3),
+        )
+        .expect("device");
+    builder
+        .add_fifo("run/fifo", &Metadata::new())
+        .expect("fifo");
+    builder
+        .add_socket("run/socket", &Metadata::new())
+        .expect("socket");
+    builder
+        .add_hard_link("etc/config.link", "etc/config")
+        .expect("hard link");
+
+    assert!(matches!(
+        builder.add_fifo("etc/config", &Metadata::new()),
+        Err(SqfsError::Exists(_))
+    ));
+    assert!(matches!(
+        builder.add_hard_link("missing.link", "missing"),
+        Err(SqfsError::NotFound(_))
+    ));
+
+    // Refused files don't leave their contents behind in the image.
+    let bytes_read = builder.stats().bytes_read();
+    let contents = [1u8; 200_000];
+    assert!(matches!(
+        builder.add_file("etc/config", &Metadata::new(), &contents[..]),
+        Err(SqfsError::Exists(_))
+    ));
+    assert!(matches!(
+        builder.add_file("etc/config/nested", &Metadata::new(), &contents[..]),
+        Err(SqfsError::WrongType(..))
+    ));
+    assert!(builder
+        .add_file("../outside", &Metadata::new(), &contents[..])
+        .is_err());
+    assert_eq!(builder.stats().bytes_read(), bytes_read);
+
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+    assert!(matches!(
+        builder.add_dir("late", &Metadata::new()),
+        Err(SqfsError::Finished)
+    ));
+
+    let archive = open_image(image);
+    let ids = archive.id_table().expect("id table");
+    assert_eq!(ids.index_to_id(1).expect("uid"), 1000);
+    assert_eq!(ids.index_to_id(2).expect("gid"), 100);
+
+    let config = archive.lookup("/etc/config").expect("lookup config");
+    assert_eq!(config.as_inode().mode().0 & 0o7777, 0o600);
+    let etc = archive.lookup("/etc").expect("lookup etc");
+    assert_eq!(etc.as_inode().mode().0 & 0o7777, 0o750);
+    let null = archive
+        .lookup_no_follow("/dev/null")
+        .expect("lookup device");
+    match null.as_inode() {
+        INode::Device(device) => assert_eq!(device.device_number(), device_number(1, 3)),
+        INode::ExtendedDevice(device) => {
+            assert_eq!(device.device_number(), device_number(1, 3))
+        }
+        _ => panic!("/dev/null should be a device"),
+    }
+
+    assert_eq!(read_file(&archive, "/etc/config.link"), b"key = value\n");
+
+    let names = archive
+        .read_dir("/run")
+        .expect("read dir")
+        .map(|entry| entry.expect("entry").name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["fifo", "socket"]);
+}
+
+#[test]
+fn build_into_file() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let path = temp.path().join("built.sqfs");
+    let file = File::open(
+        &path,
+        squashed::file::SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_OVERWRITE,
+    )
+    .expect("file");
+
+    let mut builder = ImageBuilder::new(file, &WriterOptions::new()).expect("builder");
+    builder
+        .add_file("a/b/c", &Metadata::new(), &[7u8; 10000][..])
+        .expect("file");
+    builder.finish().expect("finish");
+    assert!(matches!(builder.finish(), Err(SqfsError::Finished)));
+
+    let archive = Archive::open(&path).expect("archive");
+    assert!(archive.lookup("/a/b/c").is_ok());
+    assert_eq!(fs::metadata(&path).expect("metadata").len() % 4096, 0);
+}
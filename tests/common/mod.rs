@@ -0,0 +1,29 @@
+// Every test crate compiles this module but not all of them use every helper.
+#![allow(dead_code)]
+
+use std::io::Read;
+
+use squashed::file::File;
+use squashed::io::SqfsIo;
+use squashed::{Archive, ArchiveOptions};
+
+/// Opens an image held in memory, such as one written by `ImageBuilder::finish_into`.
+pub fn open_image(image: Vec<u8>) -> Archive {
+    let image = Box::new(image) as Box<dyn SqfsIo>;
+
+    Archive::from_file(File::from_io(image), &ArchiveOptions::new()).expect("archive")
+}
+
+/// Reads all of the file at `path` inside the image.
+pub fn read_file(archive: &Archive, path: &str) -> Vec<u8> {
+    let inode = archive.lookup(path).expect("lookup");
+    let inode = inode.as_inode();
+    let mut contents = Vec::new();
+    archive
+        .file_reader(&inode)
+        .expect("reader")
+        .read_to_end(&mut contents)
+        .expect("read");
+
+    contents
+}
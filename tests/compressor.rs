@@ -1,5 +1,8 @@
 use libsquashfs1_sys::ffi::{SQFS_COMP_FLAG, SQFS_FILE_OPEN_FLAGS};
-use squashed::compressor::CompressorConfig;
+use squashed::compressor::{
+    CompressedBlock, Compressor, CompressorConfig, CompressorOptions, CompressorType, GzipOptions,
+    GzipStrategy, Lz4Options, LzoAlgorithm, LzoOptions, XzFilter, XzOptions, ZstdOptions,
+};
 use squashed::file::File;
 use squashed::super_block::SuperBlock;
 
@@ -14,7 +17,137 @@ fn create_config() {
     let super_block = SuperBlock::read(&file).expect("super block");
 
     assert!(
-        CompressorConfig::new(&super_block, SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS).is_ok(),
+        CompressorConfig::new(
+            &file,
+            &super_block,
+            SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS
+        )
+        .is_ok(),
         "creating compressor config"
     );
 }
+
+#[test]
+fn compressor_configuration() {
+    let file = File::open(
+        "../deltagen/imgs/new.img",
+        SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_READ_ONLY,
+    )
+    .expect("opening file");
+    let super_block = SuperBlock::read(&file).expect("super block");
+    let compressor_config = CompressorConfig::new(
+        &file,
+        &super_block,
+        SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS,
+    )
+    .expect("compressor config");
+    let compressor = Compressor::new(&compressor_config).expect("compressor");
+
+    let active = compressor.get_configuration();
+    assert_eq!(active.compressor_type(), super_block.compression_id());
+    assert_eq!(active.block_size(), super_block.block_size());
+}
+
+#[test]
+fn compressor_options_round_trip() {
+    let xz = round_trip(
+        &XzOptions::new()
+            .dict_size(65536)
+            .filters(&[XzFilter::X86, XzFilter::Arm])
+            .config(131072)
+            .expect("xz config"),
+        &XzOptions::new().config(131072).expect("default xz config"),
+    );
+    assert_eq!(xz.dict_size(), Some(65536));
+    assert_eq!(xz.xz_filters(), [XzFilter::X86, XzFilter::Arm]);
+
+    let gzip = round_trip(
+        &GzipOptions::new()
+            .level(4)
+            .window_size(10)
+            .strategies(&[GzipStrategy::Filtered])
+            .config(131072)
+            .expect("gzip config"),
+        &GzipOptions::new()
+            .config(131072)
+            .expect("default gzip config"),
+    );
+    assert_eq!(gzip.window_size(), Some(10));
+    assert_eq!(gzip.gzip_strategies(), [GzipStrategy::Filtered]);
+
+    let lz4 = round_trip(
+        &Lz4Options::new()
+            .hc(true)
+            .config(131072)
+            .expect("lz4 config"),
+        &Lz4Options::new()
+            .config(131072)
+            .expect("default lz4 config"),
+    );
+    assert_ne!(lz4.flags().0 & SQFS_COMP_FLAG::SQFS_COMP_FLAG_LZ4_HC.0, 0);
+}
+
+/// Writes the options of `written` to an in-memory file and reads them back into a decompressor
+/// created from `defaults`, returning the configuration it ends up with.
+fn round_trip(written: &CompressorConfig, defaults: &CompressorConfig) -> CompressorConfig {
+    let file = File::from_io(Box::new(Vec::<u8>::new()));
+    let size = Compressor::new(written)
+        .expect("compressor")
+        .write_options(&file)
+        .expect("write options");
+    assert!(size > 0, "non-default options should be written");
+
+    let reader = Compressor::new(&defaults.clone().uncompress()).expect("decompressor");
+    reader.read_options(&file).expect("read options");
+
+    reader.get_configuration()
+}
+
+#[test]
+fn typed_compressor_options() {
+    let config = GzipOptions::new()
+        .level(6)
+        .window_size(12)
+        .strategies(&[GzipStrategy::Default, GzipStrategy::Rle])
+        .config(131072)
+        .expect("gzip config");
+
+    assert_eq!(config.compressor_type(), CompressorType::GZip);
+    assert_eq!(config.level(), 6);
+    assert!(Compressor::new(&config).is_ok(), "creating gzip compressor");
+
+    assert!(GzipOptions::new().level(10).config(131072).is_err());
+    assert!(ZstdOptions::new().level(0).config(131072).is_err());
+    assert!(XzOptions::new().dict_size(12345).config(131072).is_err());
+    assert!(XzOptions::new().dict_size(98304).config(131072).is_ok());
+    assert!(LzoOptions::new()
+        .algorithm(LzoAlgorithm::Lzo1x1)
+        .level(5)
+        .config(131072)
+        .is_err());
+}
+
+#[test]
+fn compress_and_decompress_block() {
+    let data = b"squashfs ".repeat(1024);
+    let config = GzipOptions::new().config(131072).expect("gzip config");
+    let compressor = Compressor::new(&config).expect("compressor");
+    let decompressor = Compressor::new(&config.uncompress()).expect("decompressor");
+
+    let block = compressor.compress(&data).expect("compress");
+    assert!(block.is_compressed(), "repetitive data should shrink");
+    assert_eq!(
+        decompressor
+            .decompress(block.data(), 131072)
+            .expect("decompress"),
+        data
+    );
+
+    let noise: Vec<u8> = (0..64u32)
+        .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+        .collect();
+    assert_eq!(
+        compressor.compress(&noise).expect("compress"),
+        CompressedBlock::Uncompressed(noise)
+    );
+}
@@ -0,0 +1,211 @@
+use std::io::Read;
+use std::path::Path;
+
+use squashed::builder::{ImageBuilder, Metadata};
+use squashed::convert::{sqfs_to_tar, tar_to_sqfs};
+use squashed::inode::INode;
+use squashed::writer::WriterOptions;
+use squashed::ArchiveOptions;
+use tar::{Builder, EntryType, Header};
+
+use common::{open_image, read_file};
+
+mod common;
+
+fn header(entry_type: EntryType, size: u64, mode: u32) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_uid(1000);
+    header.set_gid(1000);
+    header.set_mtime(1_600_000_000);
+    header
+}
+
+fn create_tar() -> Vec<u8> {
+    let mut builder = Builder::new(Vec::new());
+    let long_name = format!("{}/file", "long".repeat(40));
+
+    builder
+        .append_data(&mut header(EntryType::Directory, 0, 0o750), "dir/", &[][..])
+        .expect("dir");
+    builder
+        .append_pax_extensions([
+            ("SCHILY.xattr.user.comment", &b"hello"[..]),
+            ("mtime", &b"1700000000.25"[..]),
+        ])
+        .expect("pax");
+    builder
+        .append_data(
+            &mut header(EntryType::Regular, 5, 0o644),
+            "dir/plain",
+            &b"plain"[..],
+        )
+        .expect("file");
+    builder
+        .append_data(
+            &mut header(EntryType::Regular, 4, 0o600),
+            &long_name,
+            &b"long"[..],
+        )
+        .expect("long name");
+    builder
+        .append_link(
+            &mut header(EntryType::Link, 0, 0o644),
+            "dir/hard",
+            "dir/plain",
+        )
+        .expect("hard link");
+    builder
+        .append_link(
+            &mut header(EntryType::Symlink, 0, 0o777),
+            "symlink",
+            "dir/plain",
+        )
+        .expect("symlink");
+
+    let mut device = header(EntryType::Char, 0, 0o666);
+    device.set_device_major(1).expect("major");
+    device.set_device_minor(3).expect("minor");
+    builder
+        .append_data(&mut device, "dev/null", &[][..])
+        .expect("device");
+
+    // A PAX 1.0 sparse file with 4096 zero bytes followed by the stored data.
+    let mut stored = b"1\n4096\n5\n".to_vec();
+    stored.resize(512, 0);
+    stored.extend_from_slice(b"holes");
+    builder
+        .append_pax_extensions([
+            ("GNU.sparse.major", &b"1"[..]),
+            ("GNU.sparse.minor", &b"0"[..]),
+            ("GNU.sparse.name", &b"sparse"[..]),
+            ("GNU.sparse.realsize", &b"4101"[..]),
+        ])
+        .expect("sparse pax");
+    builder
+        .append_data(
+            &mut header(EntryType::Regular, stored.len() as u64, 0o644),
+            "GNUSparseFile.0/sparse",
+            &stored[..],
+        )
+        .expect("sparse");
+
+    builder.into_inner().expect("tar")
+}
+
+#[test]
+fn convert_tar() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let image = temp.path().join("image.sqfs");
+
+    tar_to_sqfs(&create_tar()[..], &image, &WriterOptions::new()).expect("convert");
+
+    let archive = ArchiveOptions::new()
+        .load_xattr_reader(true)
+        .open(&image)
+        .expect("archive");
+
+    assert_eq!(read_file(&archive, "/dir/plain"), b"plain");
+    assert_eq!(read_file(&archive, "/dir/hard"), b"plain");
+    assert_eq!(
+        read_file(&archive, &format!("/{}/file", "long".repeat(40))),
+        b"long"
+    );
+
+    let mut sparse = vec![0u8; 4096];
+    sparse.extend_from_slice(b"holes");
+    assert_eq!(read_file(&archive, "/sparse"), sparse);
+
+    let plain = archive.lookup("/dir/plain").expect("plain");
+    let plain = plain.as_inode();
+    assert_eq!(plain.modification_time(), 1_700_000_000);
+    assert_eq!(plain.number_of_hard_links(), 2);
+    let xattrs = archive
+        .xattr_reader()
+        .expect("xattr reader")
+        .get_for_inode(&plain)
+        .expect("xattrs");
+    assert_eq!(xattrs, [("user.comment".into(), b"hello".to_vec())]);
+
+    let symlink = archive.lookup_no_follow("/symlink").expect("symlink");
+    assert!(matches!(symlink.as_inode(), INode::SymbolicLink(_)));
+    let device = archive.lookup_no_follow("/dev/null").expect("device");
+    assert!(matches!(device.as_inode(), INode::Device(_)));
+}
+
+#[test]
+fn export_tar() {
+    let options = WriterOptions::new().modification_time(1000);
+    let mut builder = ImageBuilder::in_memory(&options).expect("builder");
+    let long_name = format!("sub/{}", "name".repeat(40));
+
+    builder
+        .add_file(
+            "sub/file",
+            &Metadata::new()
+                .uid(3_000_000)
+                .gid(7)
+                .xattr("user.comment", b"x"),
+            &b"contents"[..],
+        )
+        .expect("file");
+    builder
+        .add_hard_link("sub/link", "sub/file")
+        .expect("hard link");
+    builder
+        .add_symlink(&long_name, &Metadata::new(), "file")
+        .expect("symlink");
+    builder
+        .add_file("outside", &Metadata::new(), &b"skipped"[..])
+        .expect("outside");
+
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+    let archive = open_image(image);
+
+    let tar = sqfs_to_tar(&archive, "/sub", Vec::new()).expect("export");
+    let mut tar = tar::Archive::new(&tar[..]);
+    let mut seen = Vec::new();
+
+    for entry in tar.entries().expect("entries") {
+        let mut entry = entry.expect("entry");
+        let path = entry.path().expect("path").to_string_lossy().into_owned();
+        let header = entry.header().clone();
+
+        match path.as_str() {
+            "sub/" => assert!(header.entry_type().is_dir()),
+            "sub/file" => {
+                assert_eq!(entry.header().uid().expect("uid"), 3_000_000);
+                assert_eq!(entry.header().gid().expect("gid"), 7);
+                let xattrs = entry
+                    .pax_extensions()
+                    .expect("pax")
+                    .expect("records")
+                    .map(|record| record.expect("record").key().expect("key").to_string())
+                    .collect::<Vec<_>>();
+                assert!(xattrs.contains(&"SCHILY.xattr.user.comment".to_string()));
+
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).expect("read");
+                assert_eq!(contents, "contents");
+            }
+            "sub/link" => {
+                assert!(header.entry_type().is_hard_link());
+                assert_eq!(
+                    entry.link_name().expect("link name").expect("target"),
+                    Path::new("sub/file")
+                );
+            }
+            name if name == long_name => {
+                assert!(header.entry_type().is_symlink());
+            }
+            other => panic!("unexpected entry {}", other),
+        }
+
+        seen.push(path);
+    }
+
+    assert_eq!(seen.len(), 4);
+}
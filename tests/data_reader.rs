@@ -0,0 +1,57 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use squashed::compressor::Compressor;
+use squashed::compressor::CompressorConfig;
+use squashed::compressor::SQFS_COMP_FLAG;
+use squashed::data_reader::FileReader;
+use squashed::directory_reader::DirectoryReader;
+use squashed::directory_reader::{SQFS_DIR_READER_FLAGS, SQFS_TREE_FILTER_FLAGS};
+use squashed::file::File;
+use squashed::file::SQFS_FILE_OPEN_FLAGS;
+use squashed::id::IdTable;
+use squashed::inode::INode;
+use squashed::super_block::SuperBlock;
+
+#[test]
+fn read_file_contents() {
+    let file = File::open(
+        "../deltagen/imgs/new.img",
+        SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_READ_ONLY,
+    )
+    .expect("file");
+    let super_block = SuperBlock::read(&file).expect("super block");
+    let compressor_config = CompressorConfig::new(
+        &file,
+        &super_block,
+        SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS,
+    )
+    .expect("compressor config");
+    let compressor = Compressor::new(&compressor_config).expect("compressor");
+    let id_table = IdTable::read(&file, &super_block, &compressor).expect("id table");
+    let directory_reader =
+        DirectoryReader::new(&file, &super_block, &compressor, SQFS_DIR_READER_FLAGS(0))
+            .expect("directory reader");
+    let tree = directory_reader
+        .get_full_hierarchy::<PathBuf>(&id_table, None, SQFS_TREE_FILTER_FLAGS(0))
+        .expect("directory tree");
+
+    let inode = tree
+        .root()
+        .children()
+        .map(|node| node.inode())
+        .find(|inode| matches!(inode, INode::File(_) | INode::ExtendedFile(_)))
+        .expect("image should contain a file in its root");
+
+    let mut reader =
+        FileReader::new(&file, &super_block, &compressor, &inode).expect("file reader");
+
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents).expect("reading file");
+    assert_eq!(contents.len() as u64, reader.file_size());
+
+    reader.seek(SeekFrom::Start(0)).expect("seeking to start");
+    let mut again = Vec::new();
+    reader.read_to_end(&mut again).expect("reading file again");
+    assert_eq!(contents, again);
+}
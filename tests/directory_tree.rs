@@ -18,9 +18,12 @@ fn freeing_directory_tree() {
     )
     .expect("file");
     let super_block = SuperBlock::read(&file).expect("super block");
-    let compressor_config =
-        CompressorConfig::new(&super_block, SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS)
-            .expect("compressor config");
+    let compressor_config = CompressorConfig::new(
+        &file,
+        &super_block,
+        SQFS_COMP_FLAG::SQFS_COMP_FLAG_UNCOMPRESS,
+    )
+    .expect("compressor config");
     let compressor = Compressor::new(&compressor_config).expect("compressor");
     let id_table = IdTable::read(&file, &super_block, &compressor).expect("id table");
     let directory_reader =
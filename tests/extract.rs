@@ -0,0 +1,293 @@
+#![cfg(target_os = "linux")]
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use squashed::builder::{ImageBuilder, Metadata};
+use squashed::extract::{ExtractOptions, Overwrite};
+use squashed::file::File;
+use squashed::inode::INode;
+use squashed::writer::WriterOptions;
+use squashed::{Archive, ArchiveOptions, SqfsError};
+
+#[test]
+fn extract_root() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let destination = tempfile::tempdir().expect("tempdir");
+    let output = destination.path().join("root");
+
+    ExtractOptions::new()
+        .skip_special_files(true)
+        .xattrs(false)
+        .extract(&archive, "/", &output)
+        .expect("extract");
+
+    for entry in archive.read_dir("/").expect("read dir") {
+        let entry = entry.expect("entry");
+        let path = output.join(entry.name());
+
+        assert!(
+            path.symlink_metadata().is_ok(),
+            "{} should have been extracted",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn extract_refuses_existing_entries() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let destination = tempfile::tempdir().expect("tempdir");
+    let output = destination.path().join("root");
+
+    std::os::unix::fs::symlink(destination.path(), &output).expect("symlink");
+
+    assert!(
+        ExtractOptions::new()
+            .xattrs(false)
+            .extract(&archive, "/", &output)
+            .is_err(),
+        "an existing symbolic link should not be followed"
+    );
+}
+
+#[test]
+fn extract_replaces_existing_symlinks() {
+    let mut builder = ImageBuilder::in_memory(&WriterOptions::new()).expect("builder");
+    builder
+        .add_file("dir/file", &Metadata::new(), &b"inside"[..])
+        .expect("dir file");
+    builder
+        .add_file("file", &Metadata::new(), &b"inside"[..])
+        .expect("file");
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+
+    let destination = tempfile::tempdir().expect("tempdir");
+    let outside = destination.path().join("outside");
+    let output = destination.path().join("root");
+    fs::create_dir(&outside).expect("outside dir");
+    fs::write(outside.join("victim"), b"untouched").expect("victim");
+    fs::create_dir(&output).expect("output dir");
+    std::os::unix::fs::symlink(&outside, output.join("dir")).expect("dir symlink");
+    std::os::unix::fs::symlink(outside.join("victim"), output.join("file")).expect("file symlink");
+
+    ExtractOptions::new()
+        .overwrite(Overwrite::Replace)
+        .secure(true)
+        .xattrs(false)
+        .extract(&open_shared(image), "/", &output)
+        .expect("extract");
+
+    assert_eq!(
+        fs::read(outside.join("victim")).expect("victim"),
+        b"untouched"
+    );
+    assert!(
+        !outside.join("file").exists(),
+        "nothing should be written through the link"
+    );
+    assert!(!fs::symlink_metadata(output.join("dir"))
+        .expect("dir")
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read(output.join("dir/file")).expect("dir file"),
+        b"inside"
+    );
+    assert_eq!(fs::read(output.join("file")).expect("file"), b"inside");
+}
+
+#[test]
+fn extract_refuses_unsafe_names() {
+    for name in [&b".."[..], &b"a/b"[..]] {
+        let archive = open_shared(image_with_name(name));
+        let destination = tempfile::tempdir().expect("tempdir");
+
+        let result = ExtractOptions::new().xattrs(false).extract(
+            &archive,
+            "/",
+            destination.path().join("root"),
+        );
+        assert!(
+            matches!(&result, Err(SqfsError::UnsafeEntry(_, entry)) if entry.as_bytes() == name),
+            "{:?} should be refused",
+            result
+        );
+        assert!(
+            fs::read_dir(destination.path()).expect("read dir").count() <= 1,
+            "nothing but the destination should be created"
+        );
+    }
+}
+
+/// Builds an image whose root holds an empty file called `name`, which the builder refuses to
+/// create, by patching the name of a placeholder in the directory table.
+fn image_with_name(name: &[u8]) -> Vec<u8> {
+    // Names made of random bytes keep the directory table from compressing, so it is stored
+    // as is. Placeholders of `!` sort before them and are read first.
+    let placeholder = vec![b'!'; name.len()];
+    let mut state = 0x2545_f491_u32;
+    let mut random_byte = || loop {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let byte = state.to_le_bytes()[0];
+        if !matches!(byte, 0 | b'/' | b'!') {
+            return byte;
+        }
+    };
+
+    let mut builder = ImageBuilder::in_memory(&WriterOptions::new()).expect("builder");
+    builder
+        .add_file(OsStr::from_bytes(&placeholder), &Metadata::new(), &[][..])
+        .expect("placeholder");
+    for _ in 0..4 {
+        let random = (0..250).map(|_| random_byte()).collect::<Vec<_>>();
+        builder
+            .add_file(OsStr::from_bytes(&random), &Metadata::new(), &[][..])
+            .expect("random name");
+    }
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+
+    let start = open_shared(image.clone())
+        .super_block()
+        .directory_table_start();
+    let start = usize::try_from(start).expect("directory table start");
+    let offset = image[start..]
+        .windows(placeholder.len())
+        .position(|window| window == placeholder)
+        .filter(|offset| *offset < 64)
+        .expect("the directory table should be stored uncompressed");
+    image[start + offset..start + offset + name.len()].copy_from_slice(name);
+
+    image
+}
+
+#[test]
+fn extract_with_threads() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let destination = tempfile::tempdir().expect("tempdir");
+    let single = destination.path().join("single");
+    let threaded = destination.path().join("threaded");
+    let options = ExtractOptions::new().skip_special_files(true).xattrs(false);
+
+    options
+        .extract(&archive, "/", &single)
+        .expect("single threaded extract");
+    options
+        .clone()
+        .threads(4)
+        .extract(&archive, "/", &threaded)
+        .expect("threaded extract");
+
+    assert_same_tree(&single, &threaded);
+}
+
+#[test]
+fn extract_with_failing_threads() {
+    let mut builder = ImageBuilder::in_memory(&WriterOptions::new()).expect("builder");
+    for index in 0..32u8 {
+        builder
+            .add_file(
+                format!("file{}", index),
+                &Metadata::new(),
+                &vec![index + 1; 131_072][..],
+            )
+            .expect("file");
+    }
+    let mut image = Vec::new();
+    builder.finish_into(&mut image).expect("finish");
+
+    // Only the workers decompress file contents, so corrupting every data block makes all of
+    // them fail while the calling thread still finds jobs to send.
+    let archive = open_shared(image.clone());
+    let start = match archive.lookup("/file0").expect("lookup").as_inode() {
+        INode::File(file) => file.blocks_start(),
+        INode::ExtendedFile(file) => file.blocks_start(),
+        _ => panic!("file0 should be a file"),
+    };
+    let end = archive.super_block().inode_table_start();
+    image[usize::try_from(start).expect("start")..usize::try_from(end).expect("end")].fill(0xff);
+
+    let destination = tempfile::tempdir().expect("tempdir");
+    let result = ExtractOptions::new().xattrs(false).threads(2).extract(
+        &open_shared(image),
+        "/",
+        destination.path().join("root"),
+    );
+    assert!(
+        result.is_err(),
+        "failing workers should fail the extraction"
+    );
+}
+
+fn open_shared(image: Vec<u8>) -> Archive {
+    let file = File::from_io(Box::new(Arc::<[u8]>::from(image)));
+
+    Archive::from_file(file, &ArchiveOptions::new()).expect("archive")
+}
+
+/// Compares the type, permissions and contents of everything below two directories.
+fn assert_same_tree(expected: &Path, actual: &Path) {
+    let names = |directory: &Path| {
+        let mut names = fs::read_dir(directory)
+            .expect("read dir")
+            .map(|entry| entry.expect("entry").file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    };
+
+    let expected_names = names(expected);
+    assert_eq!(
+        expected_names,
+        names(actual),
+        "{} should have the same entries",
+        actual.display()
+    );
+
+    for name in expected_names {
+        let expected = expected.join(&name);
+        let actual = actual.join(&name);
+        let expected_metadata = fs::symlink_metadata(&expected).expect("expected metadata");
+        let actual_metadata = fs::symlink_metadata(&actual).expect("actual metadata");
+        let file_type = expected_metadata.file_type();
+
+        assert_eq!(
+            file_type,
+            actual_metadata.file_type(),
+            "{}",
+            actual.display()
+        );
+        assert_eq!(
+            expected_metadata.permissions().mode(),
+            actual_metadata.permissions().mode(),
+            "{}",
+            actual.display()
+        );
+
+        if file_type.is_dir() {
+            assert_same_tree(&expected, &actual);
+        } else if file_type.is_file() {
+            assert_eq!(
+                fs::read(&expected).expect("expected contents"),
+                fs::read(&actual).expect("actual contents"),
+                "{}",
+                actual.display()
+            );
+        } else if file_type.is_symlink() {
+            assert_eq!(
+                fs::read_link(&expected).expect("expected target"),
+                fs::read_link(&actual).expect("actual target"),
+                "{}",
+                actual.display()
+            );
+        }
+    }
+}
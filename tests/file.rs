@@ -1,5 +1,8 @@
+use std::sync::Arc;
+
 use libsquashfs1_sys::ffi::SQFS_FILE_OPEN_FLAGS;
 use squashed::file::File;
+use squashed::{Archive, ArchiveOptions};
 
 #[test]
 fn open_file() {
@@ -12,3 +15,57 @@ fn open_file() {
         "file should open without error"
     );
 }
+
+#[test]
+fn mmap_file() {
+    let opened = File::open(
+        "../deltagen/imgs/new.img",
+        SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_READ_ONLY,
+    )
+    .expect("file");
+    let mapped = File::mmap("../deltagen/imgs/new.img").expect("mapped file");
+
+    assert_eq!(opened.get_size(), mapped.get_size());
+    assert_eq!(
+        opened.read_at(0, 96).expect("read"),
+        mapped.read_at(0, 96).expect("mapped read")
+    );
+    assert_eq!(
+        mapped.mapped(0, 96).expect("borrowed"),
+        &*mapped.read_at(0, 96).expect("mapped read")
+    );
+    assert!(mapped.read_at(mapped.get_size(), 1).is_err());
+}
+
+#[test]
+fn file_from_io() {
+    let image = std::fs::read("../deltagen/imgs/new.img").expect("image");
+    let size = u64::try_from(image.len()).expect("size");
+    let file = File::from_io(Box::new(image));
+
+    assert_eq!(file.get_size(), size);
+    assert!(file.read_at(size, 1).is_err());
+
+    let archive = Archive::from_file(file, &ArchiveOptions::new()).expect("archive");
+    assert!(archive.lookup("/").is_ok(), "root should resolve in memory");
+}
+
+#[test]
+fn clone_shared_io() {
+    let image = std::fs::read("../deltagen/imgs/new.img").expect("image");
+
+    let owned = File::from_io(Box::new(image.clone()));
+    assert!(
+        owned.try_clone().is_err(),
+        "owned buffers should not be copied"
+    );
+
+    let shared = File::from_io(Box::new(Arc::<[u8]>::from(image)));
+    let clone = shared.try_clone().expect("clone");
+    assert_eq!(clone.get_size(), shared.get_size());
+    assert_eq!(
+        clone.read_at(0, 96).expect("clone read"),
+        shared.read_at(0, 96).expect("read")
+    );
+    assert!(shared.write_at(0, &[0]).is_err());
+}
@@ -0,0 +1,50 @@
+use squashed::builder::{ImageBuilder, Metadata};
+use squashed::writer::WriterOptions;
+use squashed::Archive;
+
+use common::open_image;
+
+mod common;
+
+#[test]
+fn image_info() {
+    let archive = Archive::open("../deltagen/imgs/new.img").expect("archive");
+    let info = archive.info().expect("info");
+    let super_block = archive.super_block();
+
+    assert_eq!(info.version(), (4, 0));
+    assert_eq!(info.block_size(), super_block.block_size());
+    assert_eq!(info.inode_count(), super_block.inode_count());
+    assert!(info.file_size() >= info.bytes_used());
+
+    let report = info.to_string();
+    assert!(report.starts_with("Found a valid SQUASHFS 4:0 superblock.\n"));
+    #[cfg(unix)]
+    assert!(
+        !report.contains(" UTC\n"),
+        "the creation time should be local time like ctime"
+    );
+    assert!(report.contains(&format!("Block size {}\n", info.block_size())));
+    assert!(report.contains(&format!("Compression {}\n", info.compressor_type())));
+    assert!(report.contains(&format!("Number of inodes {}\n", info.inode_count())));
+}
+
+#[test]
+fn duplicates_removed() {
+    let report = |deduplicate: bool| {
+        let options = WriterOptions::new().deduplicate(deduplicate);
+        let mut builder = ImageBuilder::in_memory(&options).expect("builder");
+        builder
+            .add_file("file", &Metadata::new(), &b"contents"[..])
+            .expect("file");
+
+        let mut image = Vec::new();
+        builder.finish_into(&mut image).expect("finish");
+        let archive = open_image(image);
+
+        archive.info().expect("info").to_string()
+    };
+
+    assert!(report(true).contains("Duplicates are removed\n"));
+    assert!(report(false).contains("Duplicates are not removed\n"));
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::PathBuf;
+
+use squashed::builder::{DeviceType, ImageBuilder, Metadata};
+use squashed::inode::INode;
+use squashed::pack::{GlobOptions, GlobType, PackEntry, PackFile};
+use squashed::writer::WriterOptions;
+use squashed::{Archive, SqfsError};
+
+use common::read_file;
+
+mod common;
+
+const PACK: &str = r#"
+# A comment, followed by a blank line
+
+dir /dev 0755 0 0
+nod /dev/console 0600 0 0 c 5 1
+slink /bin 0777 0 0 usr/bin
+file /etc/motd 0644 1000 100 motd.txt
+file "/etc/with space" 0600 0 0 "src dir/quoted \"name\""
+link /etc/motd.link 0 0 0 /etc/motd
+pipe /run/fifo 0644 0 0
+sock /run/socket 0644 0 0
+glob /usr/share 0644 * 0 -type f -name *.txt -- assets
+"#;
+
+#[test]
+fn parse_pack_file() {
+    let pack = PackFile::parse(PACK.as_bytes()).expect("parse");
+    let entries = pack.entries();
+
+    assert_eq!(entries.len(), 9);
+    assert_eq!(
+        entries[1],
+        PackEntry::Device {
+            path: PathBuf::from("/dev/console"),
+            meta: Metadata::new().mode(0o600).uid(0).gid(0),
+            device_type: DeviceType::Character,
+            major: 5,
+            minor: 1,
+        }
+    );
+    assert_eq!(
+        entries[4],
+        PackEntry::File {
+            path: PathBuf::from("/etc/with space"),
+            meta: Metadata::new().mode(0o600).uid(0).gid(0),
+            location: Some(PathBuf::from("src dir/quoted \"name\"")),
+        }
+    );
+    assert_eq!(
+        entries[8],
+        PackEntry::Glob {
+            path: PathBuf::from("/usr/share"),
+            mode: Some(0o644),
+            uid: None,
+            gid: Some(0),
+            options: GlobOptions {
+                file_type: Some(GlobType::File),
+                name: Some("*.txt".to_string()),
+                ..GlobOptions::default()
+            },
+            location: PathBuf::from("assets"),
+        }
+    );
+
+    assert!(matches!(
+        PackFile::parse("file /a 0999 0 0".as_bytes()),
+        Err(SqfsError::Pack(1, _))
+    ));
+    assert!(matches!(
+        PackFile::parse("\nbogus /a 0755 0 0".as_bytes()),
+        Err(SqfsError::Pack(2, _))
+    ));
+}
+
+#[test]
+fn build_from_pack_file() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let source = temp.path().join("source");
+    fs::create_dir_all(source.join("src dir")).expect("source dir");
+    fs::create_dir_all(source.join("assets/nested")).expect("assets dir");
+    fs::write(source.join("motd.txt"), b"welcome").expect("motd");
+    fs::write(source.join("src dir/quoted \"name\""), b"quoted").expect("quoted");
+    fs::write(source.join("assets/a.txt"), b"a").expect("a");
+    fs::write(source.join("assets/b.bin"), b"b").expect("b");
+    fs::write(source.join("assets/nested/c.txt"), b"c").expect("c");
+
+    let image = temp.path().join("image.sqfs");
+    let file = squashed::file::File::open(
+        &image,
+        squashed::file::SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_OVERWRITE,
+    )
+    .expect("file");
+    let mut builder = ImageBuilder::new(file, &WriterOptions::new()).expect("builder");
+    PackFile::parse(PACK.as_bytes())
+        .expect("parse")
+        .build(&mut builder, &source)
+        .expect("build");
+    builder.finish().expect("finish");
+
+    let archive = Archive::open(&image).expect("archive");
+
+    assert_eq!(read_file(&archive, "/etc/motd"), b"welcome");
+    assert_eq!(read_file(&archive, "/etc/motd.link"), b"welcome");
+    assert_eq!(read_file(&archive, "/etc/with space"), b"quoted");
+    assert_eq!(read_file(&archive, "/usr/share/a.txt"), b"a");
+    assert_eq!(read_file(&archive, "/usr/share/nested/c.txt"), b"c");
+    assert!(archive.lookup("/usr/share/b.bin").is_err());
+
+    let console = archive.lookup_no_follow("/dev/console").expect("console");
+    assert!(matches!(console.as_inode(), INode::Device(_)));
+}
@@ -0,0 +1,18 @@
+use std::io::Write;
+
+use squashed::probe;
+
+#[test]
+fn probe_embedded_image() {
+    let image = std::fs::read("../deltagen/imgs/new.img").expect("image");
+    let mut embedded = tempfile::NamedTempFile::new().expect("tempfile");
+    embedded.write_all(b"hsqs not an image").expect("decoy");
+    embedded.write_all(&[0; 4079]).expect("padding");
+    embedded.write_all(&image).expect("image");
+
+    let candidates = probe::probe_path(embedded.path()).expect("probe");
+
+    assert!(candidates.iter().all(|candidate| candidate.offset() != 0));
+    assert!(candidates.iter().any(|candidate| candidate.offset() == 4096
+        && candidate.super_block().bytes_used() <= image.len() as u64));
+}
@@ -0,0 +1,45 @@
+use libsquashfs1_sys::ffi::SQFS_FILE_OPEN_FLAGS;
+use squashed::compressor::CompressorType;
+use squashed::file::File;
+use squashed::super_block::{SuperBlock, SuperFlags};
+
+#[test]
+fn read_flags() {
+    let file = File::open(
+        "../deltagen/imgs/new.img",
+        SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_READ_ONLY,
+    )
+    .expect("opening file");
+
+    let super_block = SuperBlock::read(&file).expect("super block");
+
+    assert!(!super_block
+        .flags()
+        .contains(SuperFlags::UNCOMPRESSED_INODES));
+}
+
+#[test]
+fn create_and_write() {
+    let temp = tempfile::NamedTempFile::new().expect("temp file");
+    let file = File::open(temp.path(), SQFS_FILE_OPEN_FLAGS::SQFS_FILE_OPEN_OVERWRITE)
+        .expect("opening file");
+
+    let mut super_block =
+        SuperBlock::new(131072, 1_000_000, CompressorType::Zstd).expect("super block");
+    assert_eq!(super_block.block_log(), 17);
+    assert_eq!(super_block.compression_id(), CompressorType::Zstd);
+
+    super_block.set_flags(SuperFlags::EXPORTABLE | SuperFlags::NO_XATTRS);
+    super_block.set_inode_count(3);
+    super_block.set_id_count(1);
+    super_block.set_bytes_used(4096);
+    super_block.write(&file).expect("writing super block");
+
+    let read = SuperBlock::read(&file).expect("reading super block");
+    assert_eq!(read.flags(), SuperFlags::EXPORTABLE | SuperFlags::NO_XATTRS);
+    assert_eq!(read.inode_count(), 3);
+    assert_eq!(read.bytes_used(), 4096);
+    assert_eq!(read.modification_time(), 1_000_000);
+
+    assert!(SuperBlock::new(1000, 0, CompressorType::GZip).is_err());
+}
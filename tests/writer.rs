@@ -0,0 +1,174 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use squashed::builder::{ImageBuilder, Metadata};
+#[cfg(target_os = "linux")]
+use squashed::extract::ExtractOptions;
+use squashed::inode::INode;
+use squashed::super_block::SuperFlags;
+use squashed::writer::WriterOptions;
+use squashed::{Archive, SqfsError};
+
+use common::open_image;
+
+mod common;
+
+fn create_source(root: &Path) {
+    fs::create_dir_all(root.join("nested/deeper")).expect("create dirs");
+    fs::write(root.join("small"), b"hello squashfs").expect("small file");
+    fs::write(root.join("empty"), b"").expect("empty file");
+    fs::write(
+        root.join("nested/large"),
+        (0..600_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>(),
+    )
+    .expect("large file");
+    fs::write(root.join("nested/deeper/leaf"), b"leaf").expect("leaf file");
+    std::os::unix::fs::symlink("nested/large", root.join("link")).expect("symlink");
+    fs::hard_link(root.join("small"), root.join("nested/small")).expect("hard link");
+
+    fs::set_permissions(root.join("small"), fs::Permissions::from_mode(0o640))
+        .expect("permissions");
+    fs::File::options()
+        .write(true)
+        .open(root.join("small"))
+        .expect("open small")
+        .set_modified(UNIX_EPOCH + Duration::from_secs(1_500_000_000))
+        .expect("modification time");
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn write_directory_round_trip() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let source = temp.path().join("source");
+    let image = temp.path().join("image.sqfs");
+    let output = temp.path().join("output");
+    create_source(&source);
+
+    WriterOptions::new()
+        .xattrs(false)
+        .threads(2)
+        .write_directory(&source, &image)
+        .expect("write image");
+
+    assert_eq!(
+        fs::metadata(&image).expect("image metadata").len() % 4096,
+        0
+    );
+
+    let archive = Archive::open(&image).expect("archive");
+    let names = archive
+        .read_dir("/")
+        .expect("read dir")
+        .map(|entry| entry.expect("entry").name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["empty", "link", "nested", "small"]);
+
+    let small = archive.lookup("/small").expect("small");
+    let small = small.as_inode();
+    let linked = archive.lookup("/nested/small").expect("nested small");
+    let linked = linked.as_inode();
+    assert_eq!(small.inode_number(), linked.inode_number());
+    assert_eq!(small.number_of_hard_links(), 2);
+    assert_eq!(small.mode().0 & 0o7777, 0o640);
+    assert_eq!(small.modification_time(), 1_500_000_000);
+
+    ExtractOptions::new()
+        .xattrs(false)
+        .extract(&archive, "/", &output)
+        .expect("extract");
+
+    for file in [
+        "small",
+        "empty",
+        "nested/large",
+        "nested/deeper/leaf",
+        "nested/small",
+    ] {
+        assert_eq!(
+            fs::read(output.join(file)).expect("extracted contents"),
+            fs::read(source.join(file)).expect("source contents"),
+            "{} should round trip",
+            file
+        );
+    }
+    assert_eq!(
+        fs::read_link(output.join("link")).expect("extracted link"),
+        Path::new("nested/large")
+    );
+}
+
+#[test]
+fn refuse_empty_and_finished() {
+    let temp = tempfile::tempdir().expect("tempdir");
+    let source = temp.path().join("source");
+    fs::create_dir(&source).expect("source dir");
+
+    let mut writer = WriterOptions::new()
+        .create(temp.path().join("empty.sqfs"))
+        .expect("writer");
+    writer.add_directory(&source).expect("add directory");
+    assert!(matches!(writer.finish(), Err(SqfsError::Empty)));
+
+    create_source(&source);
+    let mut writer = WriterOptions::new()
+        .create(temp.path().join("full.sqfs"))
+        .expect("writer");
+    writer.add_directory(&source).expect("add directory");
+    writer.finish().expect("finish");
+    assert!(matches!(writer.finish(), Err(SqfsError::Finished)));
+    assert!(matches!(
+        writer.add_directory(&source),
+        Err(SqfsError::Finished)
+    ));
+}
+
+#[test]
+fn deduplicate_blocks() {
+    let contents = (0..300_000u32)
+        .map(|i| (i * 7 % 253) as u8)
+        .collect::<Vec<_>>();
+
+    let build = |deduplicate: bool| {
+        let options = WriterOptions::new().deduplicate(deduplicate);
+        let mut builder = ImageBuilder::in_memory(&options).expect("builder");
+        for name in ["first", "second"] {
+            builder
+                .add_file(name, &Metadata::new(), &contents[..])
+                .expect("file");
+        }
+
+        let mut image = Vec::new();
+        builder.finish_into(&mut image).expect("finish");
+
+        (image, builder.stats())
+    };
+
+    let (duplicated, stats) = build(false);
+    assert_eq!(stats.duplicate_blocks(), 0);
+    assert_eq!(stats.duplicate_fragments(), 0);
+
+    let (image, stats) = build(true);
+    assert_eq!(stats.bytes_read(), 600_000);
+    assert_eq!(stats.duplicate_blocks(), 2);
+    assert_eq!(stats.fragments(), 2);
+    assert_eq!(stats.duplicate_fragments(), 1);
+    assert!(image.len() < duplicated.len());
+
+    let archive = open_image(image);
+    assert!(archive
+        .super_block()
+        .flags()
+        .contains(SuperFlags::DUPLICATES));
+
+    let blocks_start = |path: &str| match archive.lookup(path).expect("lookup").as_inode() {
+        INode::File(file) => file.blocks_start(),
+        INode::ExtendedFile(file) => file.blocks_start(),
+        _ => panic!("{} should be a file", path),
+    };
+    assert_eq!(blocks_start("/first"), blocks_start("/second"));
+}